@@ -6,10 +6,35 @@ pub const SBC_DEFAULT: i16 = 0;
 pub const SBC_CYAN: i16 = 1;
 pub const SBC_BLACK: i16 = 2;
 pub const SBC_YELLOW: i16 = 3;
+pub const SBC_RED: i16 = 4;
+pub const SBC_GREEN: i16 = 5;
+pub const SBC_BLUE: i16 = 6;
+pub const SBC_MAGENTA: i16 = 7;
+pub const SBC_WHITE: i16 = 8;
 
 pub fn init_colors() {
     use_default_colors();
     init_pair(SBC_CYAN, COLOR_CYAN, COLOR_BLACK);
     init_pair(SBC_BLACK, COLOR_BLACK, COLOR_BLACK);
     init_pair(SBC_YELLOW, COLOR_YELLOW, COLOR_BLACK);
+    init_pair(SBC_RED, COLOR_RED, COLOR_BLACK);
+    init_pair(SBC_GREEN, COLOR_GREEN, COLOR_BLACK);
+    init_pair(SBC_BLUE, COLOR_BLUE, COLOR_BLACK);
+    init_pair(SBC_MAGENTA, COLOR_MAGENTA, COLOR_BLACK);
+    init_pair(SBC_WHITE, COLOR_WHITE, COLOR_BLACK);
+}
+
+// Color pair for a color name used in the genre file
+pub fn color_pair(name: &str) -> Option<i16> {
+    match name {
+        "black" => Some(SBC_BLACK),
+        "red" => Some(SBC_RED),
+        "green" => Some(SBC_GREEN),
+        "yellow" => Some(SBC_YELLOW),
+        "blue" => Some(SBC_BLUE),
+        "magenta" => Some(SBC_MAGENTA),
+        "cyan" => Some(SBC_CYAN),
+        "white" => Some(SBC_WHITE),
+        _ => None,
+    }
 }
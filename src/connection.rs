@@ -0,0 +1,415 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use mpd::error::Error;
+use mpd::error::ProtoError;
+use mpd::error::ServerError;
+
+// An mpd::Client, plus a second handle on its socket for the commands it
+// doesn't offer. The two never interleave, as mpd::Client reads every
+// response in full before returning.
+pub struct Client {
+    client: mpd::Client<Stream>,
+    socket: Stream,
+}
+
+impl Client {
+    // A ping that gives up after timeout, so a server that went away without
+    // closing the connection counts as gone rather than hanging the caller
+    pub fn ping_within(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        let pinged = self.client.ping();
+        self.socket.set_read_timeout(None)?;
+
+        pinged
+    }
+
+    // Appends files to the queue in a single command list, one round trip
+    // however many there are
+    pub fn add_all(&mut self, files: &[&str]) -> Result<(), Error> {
+        let mut commands = String::from("command_list_begin\n");
+        for file in files {
+            commands += &format!("add {}\n", quote_arg(file));
+        }
+        commands += "command_list_end\n";
+
+        self.raw_command(&commands)?;
+        Ok(())
+    }
+
+    // Songs tagged with genre, counted by mpd rather than fetched as find
+    // would
+    pub fn count_genre(&mut self, genre: &str) -> Result<usize, Error> {
+        let lines = self.raw_command(&format!("count genre {}\n", quote_arg(genre)))?;
+
+        lines.iter()
+            .find_map(|line| line.strip_prefix("songs: "))
+            .and_then(|songs| songs.parse().ok())
+            .ok_or(Error::Proto(ProtoError::NoField("songs")))
+    }
+
+    // Sends commands and returns the lines of the response before its OK.
+    // An ACK, for the first command that failed, is the error.
+    fn raw_command(&mut self, commands: &str) -> Result<Vec<String>, Error> {
+        self.socket.write_all(commands.as_bytes())?;
+        self.socket.flush()?;
+
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+
+            if line == "OK" {
+                return Ok(lines);
+            } else if line.starts_with("ACK ") {
+                return Err(Error::Server(line.parse::<ServerError>()?));
+            }
+
+            lines.push(line);
+        }
+    }
+
+    // A byte at a time so nothing meant for mpd::Client is consumed
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = Vec::new();
+        let mut byte = [0];
+
+        while byte[0] != b'\n' {
+            self.socket.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+}
+
+// An argument to a raw command, quoted and escaped as mpd expects
+fn quote_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Deref for Client {
+    type Target = mpd::Client<Stream>;
+
+    fn deref(&self) -> &mpd::Client<Stream> {
+        &self.client
+    }
+}
+
+impl DerefMut for Client {
+    fn deref_mut(&mut self) -> &mut mpd::Client<Stream> {
+        &mut self.client
+    }
+}
+
+const DEFAULT_PORT: &str = "6600";
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+// Reconnects happen on the UI thread, so an unreachable server must not
+// hold it up for the OS connect timeout
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long the ping on every tick waits before the connection is given up
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Where mpd listens
+#[derive(Clone)]
+pub enum Address {
+    Tcp(String),        // host:port
+    Unix(PathBuf),
+    Abstract(String),   // Linux abstract socket, written with a leading '@'
+}
+
+impl Address {
+    // Socket paths start with '/', '~' or '@', anything else is host:port.
+    // A host without a port gets MPD_PORT, or 6600. IPv6 hosts take a port
+    // in brackets, as in [::1]:6600, so a bare ::1 is a host without one.
+    pub fn parse(address: &str) -> Address {
+        let port = || env::var("MPD_PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string());
+
+        if let Some(name) = address.strip_prefix('@') {
+            Address::Abstract(name.to_string())
+        } else if address.starts_with('/') {
+            Address::Unix(PathBuf::from(address))
+        } else if let Some(rest) = address.strip_prefix('~') {
+            let home = env::var("HOME").unwrap_or_default();
+            Address::Unix(PathBuf::from(home + rest))
+        } else if address.starts_with('[') {
+            if address.contains("]:") {
+                Address::Tcp(address.to_string())
+            } else {
+                Address::Tcp(format!("{}:{}", address, port()))
+            }
+        } else {
+            match address.matches(':').count() {
+                0 => Address::Tcp(format!("{}:{}", address, port())),
+                1 => Address::Tcp(address.to_string()),
+                _ => Address::Tcp(format!("[{}]:{}", address, port())),
+            }
+        }
+    }
+
+    // An address optionally written as "password@address", as mpc and
+    // ncmpcpp take it. Returns the password too.
+    pub fn parse_with_password(address: &str) -> (Address, Option<String>) {
+        // "@socket" is an abstract socket, not an empty password
+        match address.find('@') {
+            Some(at) if at > 0 => (Address::parse(&address[at + 1..]), Some(address[..at].to_string())),
+            _ => (Address::parse(address), None),
+        }
+    }
+
+    // Like mpc and ncmpcpp: MPD_HOST, optionally as "password@host", and
+    // MPD_PORT, falling back on localhost. Returns the password too.
+    pub fn from_env() -> (Address, Option<String>) {
+        let host = env::var("MPD_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+
+        Address::parse_with_password(&host)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Unix(path) => write!(f, "{}", path.display()),
+            Address::Abstract(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+// Either kind of socket, so the rest of the program has one Client type
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+            Stream::Unix(s) => s.set_read_timeout(timeout),
+        }
+    }
+
+    // Another handle on the same socket
+    fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+// Remembers where mpd is and keeps a connection to it, reconnecting with
+// backoff when it drops
+pub struct Connection {
+    address: Address,
+    password: Option<String>,   // Sent again after every reconnect
+    client: Option<Client>,
+    attempts: u32,          // Failed reconnects since the connection dropped
+    retry_at: Instant,
+}
+
+impl Connection {
+    // Fails right away if mpd can't be reached, as there is no point
+    // starting up without it
+    pub fn open(address: &Address, password: Option<String>) -> Result<Connection, Error> {
+        let client = connect(address, password.as_deref())?;
+
+        Ok(Connection {
+            address: address.clone(),
+            password,
+            client: Some(client),
+            attempts: 0,
+            retry_at: Instant::now(),
+        })
+    }
+
+    pub fn client(&mut self) -> Option<&mut Client> {
+        self.client.as_mut()
+    }
+
+    // Called every tick. Notices a dropped connection and reconnects when
+    // the next attempt is due.
+    pub fn check(&mut self) {
+        if let Some(client) = &mut self.client {
+            if client.ping_within(PING_TIMEOUT).is_ok() {
+                return;
+            }
+
+            self.client = None;
+            self.attempts = 0;
+            self.retry_at = Instant::now();
+        }
+
+        if Instant::now() < self.retry_at {
+            return;
+        }
+
+        match connect(&self.address, self.password.as_deref()) {
+            Ok(client) => {
+                self.client = Some(client);
+                self.attempts = 0;
+            },
+            Err(_) => {
+                self.retry_at = Instant::now() + self.backoff();
+                self.attempts += 1;
+            },
+        }
+    }
+
+    // Doubles with every failed attempt
+    fn backoff(&self) -> Duration {
+        FIRST_RETRY.checked_mul(1 << self.attempts.min(5))
+            .unwrap_or(MAX_RETRY)
+            .min(MAX_RETRY)
+    }
+
+    // What to tell the user while disconnected
+    pub fn status(&self) -> Option<String> {
+        if self.client.is_some() {
+            return None;
+        }
+
+        let wait = self.retry_at.saturating_duration_since(Instant::now());
+        Some(format!(
+            "Lost connection to mpd at {}, retrying in {}s (attempt {})",
+            self.address,
+            wait.as_secs() + 1,
+            self.attempts + 1,
+        ))
+    }
+}
+
+fn connect(address: &Address, password: Option<&str>) -> Result<Client, Error> {
+    let stream = match address {
+        Address::Tcp(address) => connect_tcp(address).map(Stream::Tcp),
+        Address::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+        Address::Abstract(name) => connect_abstract(name).map(Stream::Unix),
+    };
+
+    // Nor should a server that accepts the connection but never answers.
+    // The timeout only covers the greeting and login, as big searches can
+    // take a while.
+    let stream = stream.map_err(Error::Io)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(Error::Io)?;
+    let handle = stream.try_clone().map_err(Error::Io)?;
+
+    let mut client = mpd::Client::new(stream)?;
+
+    if let Some(password) = password {
+        client.login(password)?;
+    }
+
+    handle.set_read_timeout(None).map_err(Error::Io)?;
+
+    Ok(Client {
+        client,
+        socket: handle,
+    })
+}
+
+// Tries each address the host resolves to in turn
+fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let mut error = io::Error::new(io::ErrorKind::NotFound, format!("{} resolves to no addresses", address));
+
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+
+    Err(error)
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &str) -> io::Result<UnixStream> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    UnixStream::connect_addr(&SocketAddr::from_abstract_name(name.as_bytes())?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(_name: &str) -> io::Result<UnixStream> {
+    Err(io::Error::new(io::ErrorKind::Other, "abstract sockets are only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(address: &str) -> String {
+        match Address::parse(address) {
+            Address::Tcp(address) => address,
+            _ => panic!("{} is not a TCP address", address),
+        }
+    }
+
+    #[test]
+    fn hosts_without_a_port_get_one() {
+        let port = env::var("MPD_PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string());
+
+        assert_eq!(tcp("localhost"), format!("localhost:{}", port));
+        assert_eq!(tcp("::1"), format!("[::1]:{}", port));
+        assert_eq!(tcp("fe80::1:2"), format!("[fe80::1:2]:{}", port));
+        assert_eq!(tcp("[::1]"), format!("[::1]:{}", port));
+    }
+
+    #[test]
+    fn ports_are_kept() {
+        assert_eq!(tcp("localhost:6601"), "localhost:6601");
+        assert_eq!(tcp("127.0.0.1:6601"), "127.0.0.1:6601");
+        assert_eq!(tcp("[::1]:6601"), "[::1]:6601");
+    }
+
+    #[test]
+    fn passwords_come_before_the_address() {
+        let (address, password) = Address::parse_with_password("secret@[::1]:6601");
+        assert_eq!(address.to_string(), "[::1]:6601");
+        assert_eq!(password.as_deref(), Some("secret"));
+
+        let (address, password) = Address::parse_with_password("@mpd");
+        assert!(matches!(address, Address::Abstract(name) if name == "mpd"));
+        assert!(password.is_none());
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+
+// What can go wrong while the interface is running. Shown on the status
+// line rather than taking the program down.
+pub enum Error {
+    Mpd(mpd::error::Error),
+    EmptyTree,      // The genre file has no genres to show
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Mpd(e) => write!(f, "mpd: {}", e),
+            Error::EmptyTree => write!(f, "The genre file has no genres in it"),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl From<mpd::error::Error> for Error {
+    fn from(e: mpd::error::Error) -> Error {
+        Error::Mpd(e)
+    }
+}
@@ -1,106 +1,489 @@
+extern crate glob;
 extern crate json;
 extern crate mpd;
 extern crate ncurses;
+extern crate regex;
+extern crate rpassword;
+extern crate toml;
+extern crate unicode_normalization;
+extern crate yaml_rust;
 
 mod style_tree;
 mod screen;
 mod colors;
+mod connection;
+mod error;
+mod watcher;
 
+use std::borrow::Cow;
 use std::env;
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::process;
 
-use mpd::Client;
+use mpd::Query;
+use mpd::Term;
 
 use ncurses::WINDOW;
 
 use colors::*;
 
+use connection::Address;
+use connection::Client;
+use connection::Connection;
+
 use style_tree::StyleTree;
+use style_tree::ExportFormat;
+use style_tree::Format;
+use style_tree::Indent;
+use style_tree::LoadError;
+use style_tree::LoadOptions;
 use style_tree::Style;
 
 use screen::Screen;
 use screen::StyleViewScreen;
+use screen::TreeEditScreen;
+
+use watcher::Watcher;
+
+// How long to wait for a key before checking on the genre file and mpd
+pub const TICK_MS: i32 = 500;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let style_path = match args.get(1) {
-        Some(path) => path,
+    let mut options = LoadOptions::default();
+    let mut export: Option<ExportFormat> = None;
+    let mut lookup: Option<&str> = None;
+    let mut unmapped = false;
+    let mut check = false;
+    let mut cross_branch_only = false;
+    let mut bootstrap = false;
+    let mut seed: Option<&str> = None;
+    let mut prompt_password = false;
+    let mut positional: Vec<&str> = Vec::new();
+
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "-i" | "--indent" => {
+                options.indent = match arg_iter.next().and_then(|s| parse_indent(s)) {
+                    Some(indent) => Some(indent),
+                    None => {
+                        eprintln!("Error: {} expects 'tab' or a number of spaces", arg);
+                        process::exit(1);
+                    },
+                };
+            },
+            "-f" | "--format" => {
+                options.format = match arg_iter.next().and_then(|s| Format::parse(s)) {
+                    Some(format) => Some(format),
+                    None => {
+                        eprintln!("Error: {} expects one of text, json, toml or yaml", arg);
+                        process::exit(1);
+                    },
+                };
+            },
+            "-e" | "--export" => {
+                export = match arg_iter.next().and_then(|s| ExportFormat::parse(s)) {
+                    Some(format) => Some(format),
+                    None => {
+                        eprintln!("Error: {} expects one of text, json, dot or markdown", arg);
+                        process::exit(1);
+                    },
+                };
+            },
+            "-w" | "--where" => {
+                lookup = match arg_iter.next() {
+                    Some(tag) => Some(tag),
+                    None => {
+                        eprintln!("Error: {} expects a genre tag", arg);
+                        process::exit(1);
+                    },
+                };
+            },
+            "-u" | "--unmapped" => unmapped = true,
+            "-P" | "--password" => prompt_password = true,
+            "-c" | "--check" => check = true,
+            "--cross-branch-only" => cross_branch_only = true,
+            "-b" | "--bootstrap" => bootstrap = true,
+            "-s" | "--seed" => {
+                seed = match arg_iter.next() {
+                    Some(path) => Some(path),
+                    None => {
+                        eprintln!("Error: {} expects a genre file", arg);
+                        process::exit(1);
+                    },
+                };
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    let style_path = match positional.first() {
+        Some(path) => *path,
         None => {
-            eprintln!("usage: {} <path> [ip:port]", args[0]);
-            eprintln!("  If no ip:port supplied, 127.0.0.1:6600 will be used");
-            return
+            print_usage(&args[0]);
+            process::exit(1);
         },
     };
 
-    let style_tree = match StyleTree::load_from_file(style_path) {
-        Ok(tree) => tree,
-        Err(e) => {
-            eprintln!("Error: Could not open style_tree");
-            eprintln!("  {}", e);
-            return
+    // An address on the command line wins over MPD_HOST
+    let (address, mut password) = match positional.get(1) {
+        Some(address) => {
+            let (address, password) = Address::parse_with_password(address);
+
+            // MPD_HOST may still hold the password for the same server
+            let (env_address, env_password) = Address::from_env();
+            if password.is_none() && env_address.to_string() == address.to_string() {
+                (address, env_password)
+            } else {
+                (address, password)
+            }
         },
+        None => Address::from_env(),
     };
 
-    let portip = match args.get(2) {
-        Some(portip) => portip,
-        None => "127.0.0.1:6600",
+    if prompt_password {
+        password = match rpassword::read_password_from_tty(Some("MPD password: ")) {
+            Ok(password) => Some(password),
+            Err(e) => {
+                eprintln!("Error: Could not read password");
+                eprintln!("  {}", e);
+                process::exit(1);
+            },
+        };
+    }
+
+    if bootstrap {
+        let seed = seed.map(|path| load_style_tree(path, &options));
+        run_bootstrap(style_path, &address, password, seed.as_ref());
+    }
+
+    let mut style_tree = load_style_tree(style_path, &options);
+
+    if let Some(format) = export {
+        print!("{}", style_tree.export(format));
+        return
+    }
+
+    if let Some(tag) = lookup {
+        let paths = style_tree.paths_for_tag(tag);
+
+        if paths.is_empty() {
+            eprintln!("{} is not in the style tree", tag);
+            process::exit(1);
+        }
+
+        for path in paths {
+            println!("{}", path.join(" > "));
+        }
+        return
+    }
+
+    // Only checked against the library when told where mpd is
+    if check && positional.get(1).is_none() {
+        run_check(&style_tree, None, cross_branch_only);
+    }
+
+    let mut connection = match Connection::open(&address, password) {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Error: Could not connect to mpd at {}", address);
+            eprintln!("  {}", e);
+            process::exit(1);
+        },
     };
+    let mpd_conn = connection.client().unwrap();
 
-    let mut mpd_conn = match Client::connect(portip) {
-        Ok(conn) => conn,
-        Err(_) => {
-            eprintln!("Error: Could not connect to mpd");
-            return
+    // Patterns in the tree can only be resolved against what the library has
+    let genres = match mpd_conn.list(&Term::Tag(Cow::Borrowed("genre")), &Query::new()) {
+        Ok(genres) => genres,
+        Err(e) => {
+            eprintln!("Error: Could not list genres");
+            eprintln!("  {}", e);
+            process::exit(1);
         },
     };
+    style_tree.resolve(&genres);
+
+    if check {
+        run_check(&style_tree, Some(&genres), cross_branch_only);
+    }
+
+    if unmapped {
+        print_unmapped(mpd_conn, &style_tree, &genres);
+        return
+    }
 
     let term = init_ncurses();
 
+    // Give the terminal back before the panic message is printed, or the
+    // message is lost and the shell left unusable
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        ncurses::endwin();
+        default_hook(info);
+    }));
+
     colors::init_colors();
     ncurses::attron(ncurses::COLOR_PAIR(SBC_DEFAULT));
 
 
-    let mut screen = StyleViewScreen::new();
+    // Switched between with the number keys
+    let mut screens: Vec<Box<dyn Screen>> = vec![
+        Box::new(StyleViewScreen::new()),
+        Box::new(TreeEditScreen::new(style_path, &options)),
+    ];
+    let mut current = 0;
+
+    // Last thing a screen failed to do, until the next key works out
+    let mut screen_error: Option<String> = None;
+
+    for screen in &mut screens {
+        let entered = screen.on_tick(mpd_conn, &style_tree)
+            .and_then(|_| screen.on_entrance(mpd_conn, &style_tree));
+        if let Err(e) = entered {
+            screen_error = Some(e.to_string());
+        }
+    }
+    screens[current].draw(ncurses::getmaxy(term), ncurses::getmaxx(term));
 
-    screen.on_tick(&mut mpd_conn, &style_tree);
-    screen.on_entrance(&mut mpd_conn, &style_tree);
-    screen.draw(ncurses::getmaxy(term), ncurses::getmaxx(term));
+    let mut watcher = Watcher::new(style_tree.files());
+    let mut reload_error: Option<String> = None;
+    let mut quitting = false;   // q was pressed once with unsaved edits
 
     loop {
         let ch = ncurses::getch();
-        if let Err(_) = test_mpd_conn(&mut mpd_conn) {
-            mpd_conn = match Client::connect("127.0.0.1:6600") {
-                Ok(conn) => conn,
-                Err(_) => {
-                    ncurses::mvaddstr(0, 0,"Cannot connect to mpd");
-                    continue
-                },
-            };
+        connection.check();
+
+        // Reloading needs the library, so changes wait for a reconnect
+        if let Some(mpd_conn) = connection.client() {
+            if watcher.changed() {
+                match reload_style_tree(style_path, &options, mpd_conn) {
+                    Ok(tree) => {
+                        style_tree = tree;
+                        watcher.watch(style_tree.files());
+                        for screen in &mut screens {
+                            if let Err(e) = screen.on_reload(mpd_conn, &style_tree) {
+                                screen_error = Some(e.to_string());
+                            }
+                        }
+                        reload_error = None;
+                    },
+                    // Keep the old tree until the file is fixed
+                    Err(e) => reload_error = Some(e),
+                }
+            }
         }
 
         if ch != ncurses::ERR {
+            if ch != 113 {
+                quitting = false;
+            }
+
             match ch {
                 ncurses::KEY_RESIZE => {
                     ncurses::erase();
                 },
-                113 => break, // q
-                _ => screen.input(ch, &mut mpd_conn, &style_tree, term),
+                113 => { // q
+                    if quitting || !screens.iter().any(|s| s.modified()) {
+                        break;
+                    }
+                    quitting = true;
+                    screen_error = Some("Unsaved changes to the genre file, press q again to quit anyway".to_string());
+                },
+                49..=57 if ((ch - 49) as usize) < screens.len() => { // 1-9
+                    current = (ch - 49) as usize;
+                },
+                // Keys that need mpd are dropped while reconnecting
+                _ => if let Some(mpd_conn) = connection.client() {
+                    screen_error = screens[current].input(ch, mpd_conn, &style_tree, term).err()
+                        .map(|e| e.to_string());
+                },
             }
         }
 
         ncurses::flushinp();
 
         ncurses::erase();
-        screen.draw(ncurses::getmaxy(term), ncurses::getmaxx(term));
+        screens[current].draw(ncurses::getmaxy(term), ncurses::getmaxx(term));
+
+        let status = connection.status()
+            .or_else(|| reload_error.clone())
+            .or_else(|| screen_error.clone());
+        if let Some(e) = status {
+            ncurses::attron(ncurses::COLOR_PAIR(SBC_RED));
+            ncurses::mvaddnstr(ncurses::getmaxy(term) - 1, 0, &e, ncurses::getmaxx(term));
+            ncurses::attroff(ncurses::COLOR_PAIR(SBC_RED));
+        }
 
         ncurses::refresh();
 
-        screen.on_tick(&mut mpd_conn, &style_tree);
+        if let Some(mpd_conn) = connection.client() {
+            if let Err(e) = screens[current].on_tick(mpd_conn, &style_tree) {
+                screen_error = Some(e.to_string());
+            }
+        }
     }
 
     shutdown_ncurses(term);
 }
 
+fn print_usage(program: &str) {
+    eprintln!("usage: {} [options] <path> [address]", program);
+    eprintln!("  address is ip:port, a socket path or @ and an abstract socket name, optionally");
+    eprintln!("  as password@address.");
+    eprintln!("  If none is supplied, MPD_HOST and MPD_PORT are used, then 127.0.0.1:6600");
+    eprintln!();
+    eprintln!("options:");
+    eprintln!("  -i, --indent <tab|N>  Indent unit of the genre file (default: detect)");
+    eprintln!("  -f, --format <fmt>    text, json, toml or yaml (default: by extension)");
+    eprintln!("  -e, --export <fmt>    Print the tree as text, json, dot or markdown and exit");
+    eprintln!("  -w, --where <tag>     Print every path in the tree a genre tag falls under and exit");
+    eprintln!("  -c, --check           Report problems in the tree, against the library if address");
+    eprintln!("                        is given, and exit non-zero if there are any");
+    eprintln!("      --cross-branch-only  With --check, only report genres repeated in separate");
+    eprintln!("                        branches, not as their own child or as tagless groupings");
+    eprintln!("  -b, --bootstrap       Write a starter genre file to <path> from the library and exit");
+    eprintln!("  -s, --seed <path>     Genre file to take the layout of known genres from when");
+    eprintln!("                        bootstrapping, e.g. the genres.txt shipped with style_freak");
+    eprintln!("  -P, --password        Ask for the mpd password. MPD_HOST=password@host also works");
+    eprintln!("  -u, --unmapped        Print library genres missing from the tree with song counts and exit");
+}
+
+fn load_style_tree(path: &str, options: &LoadOptions) -> StyleTree {
+    match StyleTree::load(path, options) {
+        Ok(tree) => tree,
+        Err(LoadError::Parse(errors)) => {
+            eprintln!("Error: Could not parse style_tree");
+            for e in errors {
+                eprintln!("  {}", e);
+            }
+            process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("Error: Could not open style_tree");
+            eprintln!("  {}", e);
+            process::exit(1);
+        },
+    }
+}
+
+// Loads the genre file again after it changed, resolved against the library
+// as it is now
+fn reload_style_tree(path: &str, options: &LoadOptions, mpd_conn: &mut Client) -> Result<StyleTree, String> {
+    let mut tree = StyleTree::load(path, options).map_err(|e| {
+        // Only room for the first error
+        let e = e.to_string();
+        format!("Could not reload {}", e.lines().next().unwrap_or(path))
+    })?;
+
+    if tree.children(tree.base()).is_empty() {
+        return Err(format!("Could not reload {}: no genres", path));
+    }
+
+    let genres = mpd_conn.list(&Term::Tag(Cow::Borrowed("genre")), &Query::new())
+        .map_err(|e| format!("Could not list genres: {}", e))?;
+    tree.resolve(&genres);
+
+    Ok(tree)
+}
+
+// Writes a starter genre file, refusing to clobber an existing one
+fn run_bootstrap(path: &str, address: &Address, password: Option<String>, seed: Option<&StyleTree>) -> ! {
+    if Path::new(path).exists() {
+        eprintln!("Error: {} already exists", path);
+        process::exit(1);
+    }
+
+    let mut connection = match Connection::open(address, password) {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Error: Could not connect to mpd at {}", address);
+            eprintln!("  {}", e);
+            process::exit(1);
+        },
+    };
+    let mpd_conn = connection.client().unwrap();
+
+    let genres = match mpd_conn.list(&Term::Tag(Cow::Borrowed("genre")), &Query::new()) {
+        Ok(genres) => genres,
+        Err(e) => {
+            eprintln!("Error: Could not list genres");
+            eprintln!("  {}", e);
+            process::exit(1);
+        },
+    };
+
+    let tree = StyleTree::bootstrap(&genres, seed);
+
+    // Written as a blank line it would just be dropped on loading
+    let empty = genres.iter().any(|g| g.is_empty());
+    let count = genres.len() - empty as usize;
+
+    let contents = format!(
+        "# Generated by style_freak --bootstrap from {} genres\n{}",
+        count,
+        tree.export(ExportFormat::Text),
+    );
+
+    if let Err(e) = fs::write(path, contents) {
+        eprintln!("Error: Could not write {}", path);
+        eprintln!("  {}", e);
+        process::exit(1);
+    }
+
+    if empty {
+        println!("Left out the empty genre tag, songs with it won't be reachable from the tree");
+    }
+    println!("Wrote {} genres to {}", count, path);
+    process::exit(0);
+}
+
+fn run_check(style_tree: &StyleTree, genres: Option<&[String]>, cross_branch_only: bool) -> ! {
+    let problems = style_tree.check(genres, cross_branch_only);
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    process::exit(if problems.is_empty() { 0 } else { 1 });
+}
+
+// Most common first, so the genres worth adding to the tree come up top
+fn print_unmapped(mpd_conn: &mut Client, style_tree: &StyleTree, genres: &[String]) {
+    let mut counts: Vec<(usize, &str)> = Vec::new();
+
+    for genre in style_tree.unmapped(genres) {
+        match mpd_conn.count_genre(genre) {
+            Ok(count) => counts.push((count, genre)),
+            Err(e) => {
+                eprintln!("Error: Could not count songs in {}", genre);
+                eprintln!("  {}", e);
+                process::exit(1);
+            },
+        }
+    }
+
+    counts.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+
+    for (count, genre) in counts {
+        println!("{}\t{}", count, genre);
+    }
+}
+
+fn parse_indent(s: &str) -> Option<Indent> {
+    match s {
+        "tab" | "tabs" => Some(Indent::Tabs),
+        s => match s.parse::<usize>() {
+            Ok(0) | Err(_) => None,
+            Ok(n) => Some(Indent::Spaces(n)),
+        },
+    }
+}
+
 fn init_ncurses() -> WINDOW {
         let window = ncurses::initscr();
 
@@ -108,6 +491,7 @@ fn init_ncurses() -> WINDOW {
         ncurses::cbreak();
         ncurses::keypad(window, true);
         //ncurses::nodelay(window, true);
+        ncurses::timeout(TICK_MS);
         ncurses::noecho();
         ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
         ncurses::setlocale(ncurses::LcCategory::all, "");
@@ -115,13 +499,6 @@ fn init_ncurses() -> WINDOW {
         window
 }
 
-fn test_mpd_conn(mpd_conn: &mut Client) -> Result<(), ()> {
-    match mpd_conn.ping() {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
-}
-
 fn shutdown_ncurses(window: WINDOW) {
     ncurses::nocbreak();
     ncurses::keypad(window, false);
@@ -47,6 +47,21 @@ impl Menu {
         }
     }
 
+    pub fn sel(&self) -> &Item {
+        &self.items[self.sel]
+    }
+
+    // Moves the selection to item if the menu has it
+    pub fn select(&mut self, item: &Item) -> bool {
+        match self.items.iter().position(|i| i == item) {
+            Some(i) => {
+                self.sel = i;
+                true
+            },
+            None => false,
+        }
+    }
+
     pub fn set_items(&mut self, items: Vec<Item>) {
         self.items = items;
         self.items.insert(0, Item::All);
@@ -4,10 +4,13 @@ use super::Item;
 
 use crate::Style;
 use crate::StyleTree;
+use crate::colors;
 
 pub struct StyleMenu {
     items: Vec<Item>,
     styles: Vec<Option<Style>>,
+    colors: Vec<Option<i16>>,
+    descriptions: Vec<Option<String>>,
     sel: usize,           // Currently selected item
 }
 
@@ -16,6 +19,8 @@ impl StyleMenu {
         StyleMenu {
             items: Vec::new(),
             styles: Vec::new(),
+            colors: Vec::new(),
+            descriptions: Vec::new(),
             sel: 0,
         }
     }
@@ -39,6 +44,21 @@ impl StyleMenu {
         &self.items[self.sel]
     }
 
+    // Moves the selection to item if the menu has it
+    pub fn select(&mut self, item: &Item) -> bool {
+        match self.items.iter().position(|i| i == item) {
+            Some(i) => {
+                self.sel = i;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.descriptions.get(self.sel).and_then(|d| d.as_deref())
+    }
+
     pub fn style_selection(&self) -> Vec<Style> {
         if *self.sel() == Item::All {
             self.styles.iter()
@@ -55,17 +75,31 @@ impl StyleMenu {
         }
     }
 
-    pub fn set_styles(&mut self, styles: Vec<Style>, tree: &StyleTree) -> bool {
+    // Lists the given children of parents, ordered as the genre file asks
+    pub fn set_styles(&mut self, parents: &[Style], mut styles: Vec<Style>, tree: &StyleTree) -> bool {
+        tree.sort_menu(parents, &mut styles);
+
         let mut items: Vec<Item> = styles.iter()
-            .map(|s| Item::from(tree.name(*s))).collect();
+            .map(|s| Item::from(tree.display_name(*s))).collect();
         let mut styles: Vec<Option<Style>> = styles.iter()
             .map(|s| Some(*s)).collect();
 
+        let mut colors: Vec<Option<i16>> = styles.iter()
+            .map(|s| s.and_then(|s| tree.color(s)).and_then(colors::color_pair))
+            .collect();
+        let mut descriptions: Vec<Option<String>> = styles.iter()
+            .map(|s| s.and_then(|s| tree.description(s)).map(|d| d.to_string()))
+            .collect();
+
         items.insert(0, Item::All);
         styles.insert(0, None);
+        colors.insert(0, None);
+        descriptions.insert(0, None);
 
         if !self.items_same(&items) {
             self.styles = styles;
+            self.colors = colors;
+            self.descriptions = descriptions;
             self.items = items;
             self.sel = 0;
 
@@ -135,6 +169,14 @@ impl StyleMenu {
         };
 
         for (i, item) in self.items.iter().enumerate().skip(first_visible) {
+            // Styles with a color of their own override the menu's color
+            let mut attrs: n::attr_t = 0;
+            let mut pair: i16 = 0;
+            if let Some(color) = self.colors[i] {
+                n::attr_get(&mut attrs, &mut pair);
+                n::color_set(color);
+            }
+
             if self.sel == i {
                 n::attron(n::A_REVERSE());
                 n::mvaddnstr(y + line, x, &item.to_string(), w);
@@ -143,6 +185,10 @@ impl StyleMenu {
                 n::mvaddnstr(y + line, x, &item.to_string(), w);
             }
 
+            if self.colors[i].is_some() {
+                n::color_set(pair);
+            }
+
             line += 1;
 
             if line > max_line {
@@ -3,8 +3,10 @@ extern crate ncurses;
 extern crate mpd;
 
 pub use style_view_screen::StyleViewScreen;
+pub use tree_edit_screen::TreeEditScreen;
 
 mod style_view_screen;
+mod tree_edit_screen;
 
 mod menu;
 
@@ -12,21 +14,35 @@ use menu::Menu;
 use menu::StyleMenu;
 use menu::Item;
 
-use mpd::Client;
+use crate::connection::Client;
+use crate::error::Error;
 
 use crate::style_tree::StyleTree;
 
 pub trait Screen {
     fn name(&self) -> &str;
 
-    fn input(&mut self, _ch: i32, _mpd_conn: &mut Client, _style_tree: &StyleTree, _display: ncurses::WINDOW) {
+    fn input(&mut self, _ch: i32, _mpd_conn: &mut Client, _style_tree: &StyleTree, _display: ncurses::WINDOW) -> Result<(), Error> {
+        Ok(())
     }
 
     fn draw(&self, win_h: i32, win_w: i32);
 
-    fn on_entrance(&mut self, _mpd_conn: &mut Client, _style_tree: &StyleTree) {
+    // Whether quitting now would lose edits
+    fn modified(&self) -> bool {
+        false
     }
 
-    fn on_tick(&mut self, _mpd_conn: &mut Client, _style_tree: &StyleTree) {
+    fn on_entrance(&mut self, _mpd_conn: &mut Client, _style_tree: &StyleTree) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_tick(&mut self, _mpd_conn: &mut Client, _style_tree: &StyleTree) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Called after the style tree has been replaced by a freshly loaded one
+    fn on_reload(&mut self, _mpd_conn: &mut Client, _style_tree: &StyleTree) -> Result<(), Error> {
+        Ok(())
     }
 }
@@ -1,12 +1,15 @@
 extern crate ncurses;
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 
-use mpd::Client;
+use crate::connection::Client;
+use crate::error::Error;
 use mpd::Query;
 use mpd::Term;
 use mpd::Song;
 
+use crate::Style;
 use crate::StyleTree;
 use crate::colors::*;
 
@@ -15,6 +18,9 @@ use super::StyleMenu;
 use super::Menu;
 use super::Item;
 
+// Songs added to the queue per command list
+const ADD_CHUNK: usize = 1000;
+
 #[derive(PartialEq, Clone)]
 enum State {
     Style(usize),
@@ -81,13 +87,25 @@ impl StyleViewScreen {
         }
     }
 
+    // Queues the fetched songs in command lists of ADD_CHUNK, rather than
+    // a round trip per song
     fn add_tracks(
         &self,
         mpd_conn: &mut Client,
-    ) {
-        for song in &self.songs {
-            mpd_conn.push(song).unwrap();
+    ) -> Result<(), Error> {
+        let total = self.songs.len();
+
+        for (i, chunk) in self.songs.chunks(ADD_CHUNK).enumerate() {
+            // Only worth showing when there is more than one list
+            if total > ADD_CHUNK {
+                show_progress(i * ADD_CHUNK, total);
+            }
+
+            let files: Vec<&str> = chunk.iter().map(|s| s.file.as_str()).collect();
+            mpd_conn.add_all(&files)?;
         }
+
+        Ok(())
     }
 
     fn prev_state(&self) -> Option<State> {
@@ -113,15 +131,20 @@ impl StyleViewScreen {
         };
 
         let mut children = Vec::new();
-        for parent in parents {
-            children.append(&mut tree.children(parent));
+        for &parent in &parents {
+            for &child in tree.children(parent) {
+                // A style linked under several selected parents is listed once
+                if !children.contains(&child) {
+                    children.push(child);
+                }
+            }
         }
 
         if children.is_empty() {
             Result::Err(())
         } else {
             let mut new_menu = StyleMenu::new();
-            new_menu.set_styles(children, tree);
+            new_menu.set_styles(&parents, children, tree);
 
             self.styles.push(new_menu);
 
@@ -129,8 +152,51 @@ impl StyleViewScreen {
         }
     }
 
-    fn fetch_artists(&mut self, mpd_conn: &mut Client, tree: &StyleTree) {
-        let genres = self.styles.last().unwrap().style_selection();
+    // Starts the menus over from the top of the tree. A tree with nothing in
+    // it still gets a menu, holding only <All>, so there is something to draw.
+    fn fetch_top_styles(&mut self, tree: &StyleTree) -> Result<(), Error> {
+        self.styles.clear();
+        self.state = State::Style(0);
+
+        if self.fetch_styles(tree).is_err() {
+            let mut menu = StyleMenu::new();
+            menu.set_styles(&[tree.base()], Vec::new(), tree);
+            self.styles.push(menu);
+
+            return Err(Error::EmptyTree);
+        }
+
+        Ok(())
+    }
+
+    // The styles the user has narrowed down to. An <All> selection stands
+    // for everything selected in the menu before it.
+    fn selected_styles(&self, tree: &StyleTree) -> Vec<Style> {
+        for menu in self.styles.iter().rev() {
+            if *menu.sel() != Item::All {
+                return menu.style_selection();
+            }
+        }
+
+        vec![tree.base()]
+    }
+
+    fn selected_genres<'a>(&self, tree: &'a StyleTree) -> Vec<&'a str> {
+        let mut genres: Vec<&str> = Vec::new();
+
+        for style in self.selected_styles(tree) {
+            for tag in tree.tags(style) {
+                if !genres.contains(&tag) {
+                    genres.push(tag);
+                }
+            }
+        }
+
+        genres
+    }
+
+    fn fetch_artists(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        let genres = self.selected_genres(tree);
 
         let mut artists = Vec::new();
 
@@ -140,19 +206,25 @@ impl StyleViewScreen {
                     &Term::Tag(Cow::Borrowed("albumartist")),
                     Query::new().and(
                         Term::Tag(Cow::Borrowed("genre")),
-                        tree.name(genre),
+                        genre,
                     ),
-                ).unwrap()
+                )?
             );
         }
 
+        // Artists with songs under several of the genres show up repeatedly
+        let mut seen = HashSet::new();
+        artists.retain(|a| seen.insert(a.clone()));
+
         self.artists.set_items(
             artists.iter().map(|s| Item::from(s)).collect()
         );
+
+        Ok(())
     }
 
-    fn fetch_albums(&mut self, mpd_conn: &mut Client, tree: &StyleTree) {
-        let genres = self.styles.last().unwrap().style_selection();
+    fn fetch_albums(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        let genres = self.selected_genres(tree);
 
         let artists = self.artists.selection();
 
@@ -165,9 +237,9 @@ impl StyleViewScreen {
                         &Term::Tag(Cow::Borrowed("Album")),
                         Query::new().and(
                             Term::Tag(Cow::Borrowed("genre")),
-                            tree.name(genre),
+                            genre,
                         )
-                    ).unwrap()
+                    )?
                 );
             }
         } else {
@@ -177,105 +249,145 @@ impl StyleViewScreen {
                         &Term::Tag(Cow::Borrowed("Album")),
                         Query::new().and(
                             Term::Tag(Cow::Borrowed("genre")),
-                            tree.name(genre),
+                            genre,
                         ).and(
                             Term::Tag(Cow::Borrowed("albumartist")),
                             *artists.first().unwrap()
                         ),
-                    ).unwrap()
+                    )?
                 );
             }
         }
 
+        let mut seen = HashSet::new();
+        albums.retain(|a| seen.insert(a.clone()));
+
         self.albums.set_items(
             albums.iter().map(|s| Item::from(s)).collect()
         );
+
+        Ok(())
     }
 
-    fn fetch(&mut self, mpd_conn: &mut Client, tree: &StyleTree) {
+    fn fetch(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
         match self.state {
             State::Style(_) => {
                 if let Err(_) = self.fetch_styles(tree) {
-                    self.fetch_artists(mpd_conn, tree);
+                    self.fetch_artists(mpd_conn, tree)?;
                 }
             },
             State::Artist => {
-                self.fetch_albums(mpd_conn, tree);
+                self.fetch_albums(mpd_conn, tree)?;
             },
             State::Album => {
-                self.fetch_tracks(mpd_conn, tree);
+                self.fetch_tracks(mpd_conn, tree)?;
             },
             State::Track => (),
         }
+
+        Ok(())
     }
 
-    fn fetch_tracks(&mut self, mpd_conn: &mut Client, tree: &StyleTree) {
-        let genres = self.styles.last().unwrap().style_selection();
+    // Fills the menus to the right of the current one, moving through them
+    fn fill_menus(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        for _ in 0..3 {
+            match self.state {
+                State::Style(_) => {
+                    if !self.styles.last().unwrap().is_empty() {
+                        // Nothing under the selection, so on to its artists
+                        // as fetch would
+                        if self.fetch_styles(tree).is_err() {
+                            self.fetch_artists(mpd_conn, tree)?;
+                            self.state = State::Artist;
+                        }
+                    } else {
+                        self.styles.pop();
+                        self.fetch_artists(mpd_conn, tree)?;
+                        self.state = State::Artist;
+                    }
+                },
+                State::Artist => {
+                    self.fetch_albums(mpd_conn, tree)?;
+                    self.state = State::Album;
+                },
+                State::Album => {
+                    self.fetch_tracks(mpd_conn, tree)?;
+                    self.state = State::Track;
+                },
+                State::Track => {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
 
+    // Runs f with the query for the songs under genre, narrowed down to the
+    // selected artist and album
+    fn track_query<T, F: FnOnce(&Query) -> T>(&self, genre: &str, f: F) -> T {
         let artists = self.artists.selection();
 
         let albums = self.albums.selection();
 
+        match (artists.last(), albums.last()) {
+            (None, None) => f(
+                Query::new().and(
+                    Term::Tag(Cow::Borrowed("Genre")),
+                    genre,
+                )
+            ),
+            (None, Some(album)) => f(
+                Query::new().and(
+                    Term::Tag(Cow::Borrowed("Genre")),
+                    genre,
+                ).and(
+                    Term::Tag(Cow::Borrowed("album")),
+                    *album
+                )
+            ),
+            (Some(artist), None) => f(
+                Query::new().and(
+                    Term::Tag(Cow::Borrowed("Genre")),
+                    genre,
+                ).and(
+                    Term::Tag(Cow::Borrowed("albumartist")),
+                    *artist
+                )
+            ),
+            (Some(artist), Some(album)) => f(
+                Query::new().and(
+                    Term::Tag(Cow::Borrowed("Genre")),
+                    genre,
+                ).and(
+                    Term::Tag(Cow::Borrowed("albumartist")),
+                    *artist
+                ).and(
+                    Term::Tag(Cow::Borrowed("album")),
+                    *album
+                )
+            ),
+        }
+    }
+
+    fn fetch_tracks(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        let genres = self.selected_genres(tree);
+
         let mut new_items = Vec::new();
+        let mut seen = HashSet::new();
 
-        if artists.is_empty() && albums.is_empty() {
-            for genre in &genres {
-                new_items.append(
-                    &mut mpd_conn.search(
-                        Query::new().and(
-                            Term::Tag(Cow::Borrowed("Genre")),
-                            tree.name(*genre),
-                        ),
-                        None
-                    ).unwrap()
-                );
-            }
-        } else {
-            if artists.is_empty() && !albums.is_empty() {
-                new_items.append(
-                    &mut mpd_conn.search(
-                        Query::new().and(
-                            Term::Tag(Cow::Borrowed("album")),
-                            *albums.last().unwrap()
-                        ),
-                        None
-                    ).unwrap()
-                );
-            } else if !artists.is_empty() && albums.is_empty() {
-                new_items.append(
-                    &mut mpd_conn.search(
-                        Query::new().and(
-                            Term::Tag(Cow::Borrowed("albumartist")),
-                            *artists.last().unwrap()
-                        ),
-                        None
-                    ).unwrap()
-                );
-            } else {
-                new_items.append(
-                    &mut mpd_conn.search(
-                        Query::new().and(
-                            Term::Tag(Cow::Borrowed("albumartist")),
-                            *artists.last().unwrap()
-                        ).and(
-                            Term::Tag(Cow::Borrowed("album")),
-                            *albums.last().unwrap()
-                        ),
-                        None
-                    ).unwrap()
-                );
-            }
-        }
+        // Constrain every search by genre so MPD does the matching. Songs
+        // can carry several genre tags, which Song::tags can't represent.
+        for genre in &genres {
+            let songs = self.track_query(genre, |query| mpd_conn.find(query, None))?;
 
-        new_items = new_items.into_iter()
-            .filter(|song| {
-                genres.iter()
-                    .any(|genre| {
-                        tree.name(*genre) == song.tags.get("Genre")
-                            .unwrap_or(&String::from("<Empty>"))
-                    })
+            // A song found through more than one of its genres is listed once
+            for song in songs {
+                if seen.insert(song.file.clone()) {
+                    new_items.push(song);
+                }
             }
-            ).collect();
+        }
 
         self.songs = new_items;
 
@@ -286,13 +398,15 @@ impl StyleViewScreen {
             }).collect();
 
         self.tracks.set_items(new_items);
+
+        Ok(())
     }
 }
 
 impl Screen for StyleViewScreen {
     fn name(&self) -> &str { &self.name }
 
-    fn input(&mut self, ch: i32, mpd_client: &mut Client, tree: &StyleTree, display: ncurses::WINDOW) {
+    fn input(&mut self, ch: i32, mpd_client: &mut Client, tree: &StyleTree, display: ncurses::WINDOW) -> Result<(), Error> {
         match ch {
              47 => { // /
                 let mut input = String::new();
@@ -313,34 +427,38 @@ impl Screen for StyleViewScreen {
                     State::Album => self.albums.search(&input),
                     State::Track => self.tracks.search(&input),
                 }
-                self.fetch(mpd_client, tree);
+                // Input mode has to be restored even if the fetch fails
+                let fetched = self.fetch(mpd_client, tree);
 
                 ncurses::cbreak();
                 ncurses::keypad(display, true);
                 ncurses::noecho();
-                ncurses::nodelay(display, true);
+                ncurses::wtimeout(display, crate::TICK_MS);
                 ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+
+                fetched?;
             },
             32 => match self.state { // Space
                 State::Style(_) => {
-                    self.fetch_artists(mpd_client, tree);
-                    self.fetch_albums(mpd_client, tree);
-                    self.fetch_tracks(mpd_client, tree);
-                    self.add_tracks(mpd_client);
+                    self.fetch_artists(mpd_client, tree)?;
+                    self.fetch_albums(mpd_client, tree)?;
+                    self.fetch_tracks(mpd_client, tree)?;
+                    self.add_tracks(mpd_client)?;
                 },
                 State::Artist => {
-                    self.fetch_albums(mpd_client, tree);
-                    self.fetch_tracks(mpd_client, tree);
-                    self.add_tracks(mpd_client);
+                    self.fetch_albums(mpd_client, tree)?;
+                    self.fetch_tracks(mpd_client, tree)?;
+                    self.add_tracks(mpd_client)?;
                 },
                 State::Album => {
-                    self.fetch_tracks(mpd_client, tree);
-                    self.add_tracks(mpd_client);
+                    self.fetch_tracks(mpd_client, tree)?;
+                    self.add_tracks(mpd_client)?;
                 },
                 State::Track => {
-                    self.fetch_tracks(mpd_client, tree);
-                    let song = self.songs.get(self.tracks.i()).unwrap();
-                    mpd_client.push(song).unwrap();
+                    self.fetch_tracks(mpd_client, tree)?;
+                    if let Some(song) = self.songs.get(self.tracks.i()) {
+                        mpd_client.push(song)?;
+                    }
                 },
             },
             104 | ncurses::KEY_LEFT => if let Some(state) = self.prev_state() { // h
@@ -348,7 +466,7 @@ impl Screen for StyleViewScreen {
             },
             108 | ncurses::KEY_RIGHT => if !self.next_menu_empty() { // l
                 self.state = self.next_state().unwrap();
-                self.fetch(mpd_client, tree);
+                self.fetch(mpd_client, tree)?;
             },
             106 | ncurses::KEY_DOWN => {
                 match self.state { // j
@@ -360,7 +478,7 @@ impl Screen for StyleViewScreen {
                     State::Album => self.albums.next(),
                     State::Track => self.tracks.next(),
                 }
-                self.fetch(mpd_client, tree)
+                self.fetch(mpd_client, tree)?
             },
             107 | ncurses::KEY_UP => {
                 match self.state { // k
@@ -372,41 +490,80 @@ impl Screen for StyleViewScreen {
                     State::Album => self.albums.prev(),
                     State::Track => self.tracks.prev(),
                 }
-                self.fetch(mpd_client, tree)
+                self.fetch(mpd_client, tree)?
             },
             _ => (),
         }
+
+        Ok(())
     }
 
-    fn on_entrance(&mut self, mpd_conn: &mut Client, tree: &StyleTree) {
-            self.fetch_styles(tree).unwrap();
+    fn on_entrance(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+            self.fetch_top_styles(tree)?;
 
             let state = self.state.clone();
-            for _ in 0..3 {
-                match self.state {
-                    State::Style(_) => {
-                        if !self.styles.last().unwrap().is_empty() {
-                            self.fetch_styles(tree);
-                        } else {
-                            self.styles.pop();
-                            self.fetch_artists(mpd_conn, tree);
-                            self.state = State::Artist;
-                        }
-                    },
-                    State::Artist => {
-                        self.fetch_albums(mpd_conn, tree);
-                        self.state = State::Album;
-                    },
-                    State::Album => {
-                        self.fetch_tracks(mpd_conn, tree);
-                        self.state = State::Track;
-                    },
-                    State::Track => {
-                        break;
-                    }
-                }
-            }
+            let filled = self.fill_menus(mpd_conn, tree);
             self.state = state;
+
+            filled
+    }
+
+    // Style ids from the old tree mean nothing in the new one, so the menus
+    // are rebuilt and the old selections found again by name. Where the path
+    // no longer exists the user is left at the deepest part that does.
+    fn on_reload(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        let path: Vec<Item> = self.styles.iter().map(|m| m.sel().clone()).collect();
+        let artist = if self.artists.is_empty() { None } else { Some(self.artists.sel().clone()) };
+        let album = if self.albums.is_empty() { None } else { Some(self.albums.sel().clone()) };
+        let track = if self.tracks.is_empty() { None } else { Some(self.tracks.sel().clone()) };
+
+        let depth = match self.state {
+            State::Style(i) => i + 1,
+            _ => path.len(),
+        };
+
+        let state = self.state.clone();
+        self.fetch_top_styles(tree)?;
+        self.state = state;
+
+        let mut matched = 0;
+        while matched < depth && self.styles[matched].select(&path[matched]) {
+            matched += 1;
+            if matched < depth && self.fetch_styles(tree).is_err() {
+                break;
+            }
+        }
+
+        let current = match self.state {
+            State::Style(_) => Some(matched.min(depth - 1)),
+            _ if matched < depth => Some(matched),
+            _ => None,
+        };
+
+        match current {
+            Some(i) => {
+                let i = i.min(self.styles.len() - 1);
+                self.state = State::Style(i);
+                self.styles.truncate(i + 1);
+                self.fetch(mpd_conn, tree)?;
+            },
+            None => {
+                self.fetch_artists(mpd_conn, tree)?;
+                if let Some(artist) = artist {
+                    self.artists.select(&artist);
+                }
+                self.fetch_albums(mpd_conn, tree)?;
+                if let Some(album) = album {
+                    self.albums.select(&album);
+                }
+                self.fetch_tracks(mpd_conn, tree)?;
+                if let Some(track) = track {
+                    self.tracks.select(&track);
+                }
+            },
+        }
+
+        Ok(())
     }
 
     fn draw(&self, win_h: i32, win_w: i32) {
@@ -495,5 +652,22 @@ impl Screen for StyleViewScreen {
         }
 
         ncurses::attroff(ncurses::COLOR_PAIR(SBC_CYAN));
+
+        if let State::Style(i) = self.state {
+            if let Some(description) = self.styles[i].description() {
+                ncurses::mvaddnstr(win_h - 1, 0, description, win_w);
+            }
+        }
     }
 }
+
+// Drawn straight away, as the screen isn't redrawn until the add is done
+fn show_progress(done: usize, total: usize) {
+    let win_h = ncurses::getmaxy(ncurses::stdscr());
+    let win_w = ncurses::getmaxx(ncurses::stdscr());
+
+    ncurses::mv(win_h - 1, 0);
+    ncurses::clrtoeol();
+    ncurses::mvaddnstr(win_h - 1, 0, &format!("Adding songs to the queue: {}/{}", done, total), win_w);
+    ncurses::refresh();
+}
@@ -0,0 +1,344 @@
+extern crate ncurses;
+
+use std::borrow::Cow;
+
+use crate::connection::Client;
+use crate::error::Error;
+use mpd::Query;
+use mpd::Term;
+
+use crate::style_tree::Entry;
+use crate::style_tree::LoadOptions;
+use crate::style_tree::Source;
+use crate::StyleTree;
+use crate::colors::*;
+
+use super::Screen;
+
+#[derive(PartialEq)]
+enum Focus {
+    Tree,
+    Unmapped,
+}
+
+// Edits the genre file itself rather than the loaded tree, so comments and
+// layout survive. Saving writes the file, which the running tree then picks
+// up like any other change to it.
+pub struct TreeEditScreen {
+    name: String,
+    path: String,
+    options: LoadOptions,
+
+    source: Option<Source>,
+    entries: Vec<Entry>,
+    sel: usize,
+    modified: bool,
+
+    unmapped: Vec<String>,
+    unmapped_sel: usize,
+    holding: Option<String>,    // Unmapped genre waiting to be dropped on a genre
+    focus: Focus,
+
+    message: Option<String>,
+}
+
+impl TreeEditScreen {
+    pub fn new(path: &str, options: &LoadOptions) -> TreeEditScreen {
+        TreeEditScreen {
+            name: String::from("Genre Editor"),
+            path: path.to_string(),
+            options: options.clone(),
+            source: None,
+            entries: Vec::new(),
+            sel: 0,
+            modified: false,
+            unmapped: Vec::new(),
+            unmapped_sel: 0,
+            holding: None,
+            focus: Focus::Tree,
+            message: None,
+        }
+    }
+
+    fn open(&mut self) {
+        match Source::open(&self.path, &self.options) {
+            Ok(source) => {
+                self.source = Some(source);
+                self.modified = false;
+                self.message = None;
+            },
+            Err(e) => {
+                self.source = None;
+                self.message = Some(format!("Can't edit {}: {}", self.path, e));
+            },
+        }
+
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.entries = match &self.source {
+            Some(source) => source.entries(),
+            None => Vec::new(),
+        };
+
+        if self.sel >= self.entries.len() {
+            self.sel = self.entries.len().saturating_sub(1);
+        }
+    }
+
+    fn fetch_unmapped(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        let genres = mpd_conn.list(&Term::Tag(Cow::Borrowed("genre")), &Query::new())?;
+
+        self.unmapped = tree.unmapped(&genres).into_iter()
+            .map(|g| g.to_string())
+            .collect();
+        self.unmapped.sort();
+
+        if self.unmapped_sel >= self.unmapped.len() {
+            self.unmapped_sel = self.unmapped.len().saturating_sub(1);
+        }
+
+        Ok(())
+    }
+
+    // Runs an edit on the file with the selected entry. The edit returns
+    // the entry to select afterwards, or None if it couldn't be made.
+    fn edit<F: FnOnce(&mut Source, Option<usize>) -> Option<usize>>(&mut self, f: F) {
+        let sel = if self.entries.is_empty() { None } else { Some(self.sel) };
+
+        if let Some(source) = &mut self.source {
+            if let Some(sel) = f(source, sel) {
+                self.sel = sel;
+                self.modified = true;
+                self.message = None;
+            }
+        }
+
+        self.refresh();
+    }
+
+    fn rename(&mut self, name: &str) {
+        if let Some(source) = &mut self.source {
+            match source.rename(self.sel, name) {
+                Ok(()) => {
+                    self.modified = true;
+                    self.message = None;
+                },
+                Err(e) => self.message = Some(e),
+            }
+        }
+
+        self.refresh();
+    }
+
+    fn save(&mut self) {
+        let result = match &self.source {
+            Some(source) => source.save(),
+            None => return,
+        };
+
+        match result {
+            Ok(()) => {
+                self.modified = false;
+                self.message = Some(format!("Saved {}", self.path));
+            },
+            Err(e) => self.message = Some(format!("Not saved: {}", e)),
+        }
+    }
+
+    fn input_tree(&mut self, ch: i32, display: ncurses::WINDOW) {
+        match ch {
+            106 | ncurses::KEY_DOWN if self.sel + 1 < self.entries.len() => { // j
+                self.sel += 1;
+            },
+            107 | ncurses::KEY_UP if self.sel > 0 => { // k
+                self.sel -= 1;
+            },
+            97 | 65 => { // a, A
+                let name = prompt("Add: ", display);
+                if !name.is_empty() {
+                    self.edit(|source, sel| source.add(sel, ch == 65, &name));
+                }
+            },
+            114 if !self.entries.is_empty() => { // r
+                let name = prompt("Rename to: ", display);
+                if !name.is_empty() {
+                    self.rename(&name);
+                }
+            },
+            100 if !self.entries.is_empty() => { // d
+                self.edit(|source, sel| {
+                    source.delete(sel.unwrap());
+                    sel
+                });
+            },
+            74 if !self.entries.is_empty() => { // J
+                self.edit(|source, sel| source.move_down(sel.unwrap()));
+            },
+            75 if !self.entries.is_empty() => { // K
+                self.edit(|source, sel| source.move_up(sel.unwrap()));
+            },
+            62 if !self.entries.is_empty() => { // >
+                self.edit(|source, sel| source.indent(sel.unwrap()));
+            },
+            60 if !self.entries.is_empty() => { // <
+                self.edit(|source, sel| source.outdent(sel.unwrap()));
+            },
+            10 | ncurses::KEY_ENTER if self.holding.is_some() => { // Enter
+                let genre = self.holding.take().unwrap();
+                self.edit(|source, sel| source.add(sel, true, &genre));
+                self.unmapped.retain(|g| *g != genre);
+                if self.unmapped_sel >= self.unmapped.len() {
+                    self.unmapped_sel = self.unmapped.len().saturating_sub(1);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn input_unmapped(&mut self, ch: i32) {
+        match ch {
+            106 | ncurses::KEY_DOWN if self.unmapped_sel + 1 < self.unmapped.len() => { // j
+                self.unmapped_sel += 1;
+            },
+            107 | ncurses::KEY_UP if self.unmapped_sel > 0 => { // k
+                self.unmapped_sel -= 1;
+            },
+            10 | ncurses::KEY_ENTER if self.unmapped_sel < self.unmapped.len() => { // Enter
+                self.holding = Some(self.unmapped[self.unmapped_sel].clone());
+                self.focus = Focus::Tree;
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Screen for TreeEditScreen {
+    fn name(&self) -> &str { &self.name }
+
+    fn input(&mut self, ch: i32, _mpd_client: &mut Client, _tree: &StyleTree, display: ncurses::WINDOW) -> Result<(), Error> {
+        match ch {
+            9 => self.focus = match self.focus { // Tab
+                Focus::Tree => Focus::Unmapped,
+                Focus::Unmapped => Focus::Tree,
+            },
+            27 => self.holding = None, // Esc
+            119 => self.save(), // w
+            _ => match self.focus {
+                Focus::Tree => self.input_tree(ch, display),
+                Focus::Unmapped => self.input_unmapped(ch),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn modified(&self) -> bool {
+        self.modified
+    }
+
+    fn on_entrance(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        self.open();
+        self.fetch_unmapped(mpd_conn, tree)
+    }
+
+    fn on_reload(&mut self, mpd_conn: &mut Client, tree: &StyleTree) -> Result<(), Error> {
+        // Don't throw away edits because the file changed underneath them
+        if self.modified {
+            self.message = Some(format!("{} changed on disk, saving will overwrite it", self.path));
+        } else {
+            let message = self.message.take();
+            self.open();
+            if self.message.is_none() {
+                self.message = message;
+            }
+        }
+
+        self.fetch_unmapped(mpd_conn, tree)
+    }
+
+    fn draw(&self, win_h: i32, win_w: i32) {
+        let tree_w = win_w * 2 / 3;
+        let list_h = win_h - 3;
+
+        let mut title = format!("{}: {}", self.name, self.path);
+        if self.modified {
+            title += " [modified]";
+        }
+        if let Some(genre) = &self.holding {
+            title += &format!(" [dropping {}]", genre);
+        }
+        ncurses::mvaddnstr(0, 0, &title, tree_w);
+        ncurses::mvaddnstr(0, tree_w + 1, "Unmapped", win_w - tree_w - 1);
+        ncurses::mvhline(1, 0, ncurses::ACS_HLINE(), win_w);
+        ncurses::mvvline(0, tree_w, ncurses::ACS_VLINE(), win_h - 1);
+
+        let lines: Vec<String> = self.entries.iter()
+            .map(|e| format!("{}{}", "  ".repeat(e.depth), e.name))
+            .collect();
+        draw_list(&lines, self.sel, self.focus == Focus::Tree, 2, 0, list_h, tree_w);
+        draw_list(
+            &self.unmapped,
+            self.unmapped_sel,
+            self.focus == Focus::Unmapped,
+            2,
+            tree_w + 1,
+            list_h,
+            win_w - tree_w - 1,
+        );
+
+        match &self.message {
+            Some(message) => {
+                ncurses::attron(ncurses::COLOR_PAIR(SBC_RED));
+                ncurses::mvaddnstr(win_h - 1, 0, message, win_w);
+                ncurses::attroff(ncurses::COLOR_PAIR(SBC_RED));
+            },
+            None => {
+                ncurses::mvaddnstr(
+                    win_h - 1,
+                    0,
+                    "a/A add  r rename  d delete  J/K move  </> outdent/indent  Tab unmapped  Enter drop  w save",
+                    win_w,
+                );
+            },
+        }
+    }
+}
+
+fn draw_list(items: &[String], sel: usize, focused: bool, y: i32, x: i32, h: i32, w: i32) {
+    let h = h.max(0) as usize;
+    let first_visible = sel.saturating_sub(h / 2);
+
+    for (line, (i, item)) in items.iter().enumerate().skip(first_visible).take(h).enumerate() {
+        if i == sel && focused {
+            ncurses::attron(ncurses::A_REVERSE());
+            ncurses::mvaddnstr(y + line as i32, x, item, w);
+            ncurses::attroff(ncurses::A_REVERSE());
+        } else {
+            ncurses::mvaddnstr(y + line as i32, x, item, w);
+        }
+    }
+}
+
+fn prompt(label: &str, display: ncurses::WINDOW) -> String {
+    let mut input = String::new();
+
+    ncurses::nocbreak();
+    ncurses::echo();
+    ncurses::nodelay(display, false);
+    ncurses::keypad(display, false);
+    ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_VISIBLE);
+    ncurses::mv(0, 0);
+    ncurses::clrtoeol();
+    ncurses::addstr(label);
+    ncurses::getstr(&mut input);
+
+    ncurses::cbreak();
+    ncurses::keypad(display, true);
+    ncurses::noecho();
+    ncurses::wtimeout(display, crate::TICK_MS);
+    ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+
+    input.trim().to_string()
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use super::matcher::Matcher;
+use super::Match;
+use super::Style;
+use super::StyleTree;
+
+// Endings shared by families of single word genres, e.g. Dubstep, Brostep
+const SUFFIXES: &[&str] = &["step", "core", "wave", "hop", "gaze", "punk", "tronica"];
+
+const UNSORTED: &str = "Unsorted";
+
+// Genre tree under construction. Kept separate from StyleTree so it can be
+// sorted before any styles are added.
+struct Node {
+    name: String,
+    is_tag: bool,   // A genre tag in the library, not just a grouping
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(name: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            is_tag: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&mut self, name: &str) -> &mut Node {
+        let i = match self.children.iter().position(|c| c.name == name) {
+            Some(i) => i,
+            None => {
+                self.children.push(Node::new(name));
+                self.children.len() - 1
+            },
+        };
+
+        &mut self.children[i]
+    }
+
+    fn add_tag(&mut self, path: &[&str]) {
+        let mut node = self;
+        for name in path {
+            node = node.child(name);
+        }
+        node.is_tag = true;
+    }
+
+    fn finish(&mut self) {
+        for child in &mut self.children {
+            child.finish();
+        }
+
+        self.children.sort_by(|a, b| {
+            (a.name == UNSORTED).cmp(&(b.name == UNSORTED))
+                .then(a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+    }
+}
+
+impl StyleTree {
+    // Starter tree for the genre tags in a library. Tags the seed tree knows
+    // go where the seed has them, the rest are grouped by a shared last word
+    // or ending, and anything left over goes under Unsorted. Songs with an
+    // empty genre tag have no genre to file, so that tag is left out.
+    pub fn bootstrap(genres: &[String], seed: Option<&StyleTree>) -> StyleTree {
+        let mut root = Node::new("Root");
+        let mut rest: Vec<&str> = Vec::new();
+
+        for genre in genres.iter().filter(|g| !g.is_empty()) {
+            let path = seed.and_then(|seed| {
+                let style = *seed.find_tag(genre).first()?;
+                let mut path = seed.path(style);
+                path.pop();
+                Some(path)
+            });
+
+            match path {
+                Some(mut path) => {
+                    path.push(genre);
+                    root.add_tag(&path);
+                },
+                None => rest.push(genre),
+            }
+        }
+
+        let mut groups: HashMap<String, Vec<&str>> = HashMap::new();
+        for genre in rest {
+            groups.entry(group_key(genre)).or_default().push(genre);
+        }
+
+        for (key, members) in groups {
+            let group = match members.len() {
+                1 => UNSORTED.to_string(),
+                _ => group_name(&key, genres),
+            };
+
+            // The genre the group is named after heads it rather than
+            // being repeated under it
+            for genre in members {
+                if genre == group {
+                    root.add_tag(&[genre]);
+                } else {
+                    root.add_tag(&[&group, genre]);
+                }
+            }
+        }
+
+        root.finish();
+
+        let mut tree = StyleTree::new();
+        let base = tree.base();
+        tree.add_nodes(base, &root.children, Match::Leaves);
+        tree
+    }
+
+    // Only leaves are matched by default, so a genre tag that ended up with
+    // children is matched with {match=any}, and groupings under it set back
+    // to leaves
+    fn add_nodes(&mut self, parent: Style, nodes: &[Node], matching: Match) {
+        for node in nodes {
+            let style = self.add_style(parent, &node.name);
+            self.set_spellings(style, Vec::new(), vec![Matcher::Exact(node.name.clone())]);
+
+            let mut matching = matching;
+            if !node.children.is_empty() {
+                let wanted = if node.is_tag { Match::Any } else { Match::Leaves };
+                if wanted != matching {
+                    self.node_matching[style] = Some(wanted);
+                    matching = wanted;
+                }
+            }
+
+            self.add_nodes(style, &node.children, matching);
+        }
+    }
+}
+
+// Genres with the same key are grouped together. The key is the last word
+// of a genre, the ending it shares with others, or failing that the whole
+// genre, so that e.g. House heads Deep House and French House.
+fn group_key(genre: &str) -> String {
+    let genre = genre.to_lowercase();
+
+    if let Some(word) = genre.split_whitespace().last() {
+        if word != genre.trim() {
+            return word.to_string();
+        }
+    }
+
+    match SUFFIXES.iter().find(|s| genre.ends_with(*s) && genre != **s) {
+        Some(suffix) => suffix.to_string(),
+        None => genre,
+    }
+}
+
+// Spelled like the library does if it has a tag for it
+fn group_name(key: &str, genres: &[String]) -> String {
+    if let Some(genre) = genres.iter().find(|g| g.to_lowercase() == key) {
+        return genre.clone();
+    }
+
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_text;
+    use super::super::ExportFormat;
+    use super::*;
+
+    fn genres(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn reload(tree: &StyleTree) -> StyleTree {
+        parse_text(&tree.export(ExportFormat::Text)).unwrap()
+    }
+
+    #[test]
+    fn tag_with_children_matches_any() {
+        let library = genres(&["House", "Deep House", "French House", "Rock"]);
+        let tree = StyleTree::bootstrap(&library, None);
+
+        assert_eq!(
+            tree.export(ExportFormat::Text),
+            "House {match=any}\n\tDeep House\n\tFrench House\nUnsorted\n\tRock\n",
+        );
+    }
+
+    #[test]
+    fn starter_file_loads_and_passes_check() {
+        let library = genres(&[
+            "House", "Deep House", "Dubstep", "Brostep", "Rock #1", "Pop | Rock",
+            "%weird", "@home", "R&B {live}", "A > B",
+        ]);
+        let mut tree = reload(&StyleTree::bootstrap(&library, None));
+        tree.resolve(&library);
+
+        assert!(tree.check(Some(&library), false).is_empty());
+        assert!(tree.unmapped(&library).is_empty());
+    }
+
+    #[test]
+    fn empty_tag_is_left_out() {
+        let library = genres(&["", "House", "Rock"]);
+        let tree = StyleTree::bootstrap(&library, None);
+
+        assert_eq!(tree.export(ExportFormat::Text), "Unsorted\n\tHouse\n\tRock\n");
+    }
+
+    #[test]
+    fn groupings_under_a_tag_match_leaves() {
+        let seed = parse_text("House\n\tFusion\n\t\tTech House\n").unwrap();
+
+        let library = genres(&["House", "Tech House"]);
+        let tree = StyleTree::bootstrap(&library, Some(&seed));
+        let text = tree.export(ExportFormat::Text);
+
+        assert!(text.starts_with("House {match=any}\n\tFusion {match=leaves}\n\t\tTech House\n"));
+    }
+}
@@ -0,0 +1,450 @@
+use json::JsonValue;
+use yaml_rust::Yaml;
+use yaml_rust::YamlLoader;
+
+use super::parser::link_references;
+use super::parser::parse_spellings;
+use super::parser::set_attribute;
+use super::parser::set_option;
+use super::parser::Reference;
+use super::Format;
+use super::LoadError;
+use super::ParseError;
+use super::ParseErrorKind;
+use super::Style;
+use super::StyleTree;
+
+// Format independent view of a JSON, TOML or YAML document
+enum Value {
+    Null,
+    Scalar(String),
+    List(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn from_json(value: &JsonValue) -> Value {
+        match value {
+            JsonValue::Null => Value::Null,
+            JsonValue::Array(list) => Value::List(list.iter().map(Value::from_json).collect()),
+            JsonValue::Object(map) => Value::Map(
+                map.iter().map(|(k, v)| (k.to_string(), Value::from_json(v))).collect()
+            ),
+            scalar => match scalar.as_str() {
+                Some(s) => Value::Scalar(s.to_string()),
+                None => Value::Scalar(scalar.dump()),
+            },
+        }
+    }
+
+    fn from_toml(value: &toml::Value) -> Value {
+        match value {
+            toml::Value::String(s) => Value::Scalar(s.clone()),
+            toml::Value::Array(list) => Value::List(list.iter().map(Value::from_toml).collect()),
+            toml::Value::Table(map) => Value::Map(
+                map.iter().map(|(k, v)| (k.clone(), Value::from_toml(v))).collect()
+            ),
+            scalar => Value::Scalar(scalar.to_string()),
+        }
+    }
+
+    fn from_yaml(value: &Yaml) -> Value {
+        match value {
+            Yaml::Null | Yaml::BadValue => Value::Null,
+            Yaml::String(s) | Yaml::Real(s) => Value::Scalar(s.clone()),
+            Yaml::Integer(i) => Value::Scalar(i.to_string()),
+            Yaml::Boolean(b) => Value::Scalar(b.to_string()),
+            Yaml::Array(list) => Value::List(list.iter().map(Value::from_yaml).collect()),
+            Yaml::Hash(map) => Value::Map(
+                map.iter()
+                    .map(|(k, v)| (Value::from_yaml(k).scalar().unwrap_or_default(), Value::from_yaml(v)))
+                    .collect()
+            ),
+            Yaml::Alias(_) => Value::Null,
+        }
+    }
+
+    fn scalar(&self) -> Option<String> {
+        match self {
+            Value::Scalar(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    // A single string, or a list of them
+    fn strings(&self) -> Option<Vec<String>> {
+        match self {
+            Value::Scalar(s) => Some(vec![s.clone()]),
+            Value::List(list) => list.iter().map(|v| v.scalar()).collect(),
+            _ => None,
+        }
+    }
+}
+
+struct Loader<'a> {
+    tree: &'a mut StyleTree,
+    file: String,
+    references: Vec<Reference>,
+    errors: Vec<ParseError>,
+}
+
+// Builds tree from a JSON, TOML or YAML document. The document is either a
+// list of genres, or a map holding tree wide options and a "genres" list.
+// Each genre is a name, or a map with "name", "aliases", "children", any of
+// the attributes allowed in text files, or just "link" to link in a genre
+// defined elsewhere.
+pub fn load(tree: &mut StyleTree, file: &str, source: &str, format: Format) -> Result<(), LoadError> {
+    let root = parse(source, format).map_err(|(line, column, message)| {
+        LoadError::Parse(vec![ParseError {
+            file: file.to_string(),
+            line,
+            column,
+            kind: ParseErrorKind::Syntax(message),
+        }])
+    })?;
+
+    let base = tree.base();
+    let mut loader = Loader {
+        tree,
+        file: file.to_string(),
+        references: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    loader.load_root(&root, base);
+
+    let references = std::mem::take(&mut loader.references);
+    for (_, kind) in link_references(loader.tree, references) {
+        loader.error("", kind);
+    }
+
+    if loader.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(LoadError::Parse(loader.errors))
+    }
+}
+
+// Returns the line, column and message of a syntax error
+fn parse(source: &str, format: Format) -> Result<Value, (usize, usize, String)> {
+    match format {
+        Format::Json => match json::parse(source) {
+            Ok(value) => Ok(Value::from_json(&value)),
+            Err(json::Error::UnexpectedCharacter { ch, line, column }) => {
+                Err((line, column, format!("unexpected character '{}'", ch)))
+            },
+            Err(e) => Err((0, 0, e.to_string())),
+        },
+        Format::Toml => match source.parse::<toml::Value>() {
+            Ok(value) => Ok(Value::from_toml(&value)),
+            Err(e) => {
+                let (line, column) = e.line_col().map(|(l, c)| (l + 1, c + 1)).unwrap_or((0, 0));
+                Err((line, column, e.to_string()))
+            },
+        },
+        Format::Yaml => match YamlLoader::load_from_str(source) {
+            Ok(docs) => Ok(docs.first().map(Value::from_yaml).unwrap_or(Value::Null)),
+            Err(e) => Err((e.marker().line(), e.marker().col() + 1, e.to_string())),
+        },
+        Format::Text => unreachable!(),
+    }
+}
+
+impl<'a> Loader<'a> {
+    fn load_root(&mut self, root: &Value, base: Style) {
+        match root {
+            Value::List(_) => self.load_children(root, base, ""),
+            Value::Map(map) => {
+                for (key, value) in map {
+                    if key == "genres" {
+                        self.load_children(value, base, "");
+                        continue;
+                    }
+
+                    let value = match value.strings() {
+                        Some(strings) => strings.join(" "),
+                        None => {
+                            self.bad_document("", &format!("\"{}\" must be a string or a list of strings", key));
+                            continue;
+                        },
+                    };
+
+                    if let Err(kind) = set_option(self.tree, key, &value) {
+                        self.error("", kind);
+                    }
+                }
+            },
+            _ => self.bad_document("", "expected a list of genres or a map with a \"genres\" list"),
+        }
+    }
+
+    fn load_children(&mut self, children: &Value, parent: Style, path: &str) {
+        match children {
+            Value::List(list) => {
+                for child in list {
+                    self.load_style(child, parent, path);
+                }
+            },
+            _ => self.bad_document(path, "\"children\" must be a list"),
+        }
+    }
+
+    fn load_style(&mut self, value: &Value, parent: Style, parent_path: &str) {
+        let empty = Vec::new();
+        let fields = match value {
+            Value::Scalar(_) => &empty,
+            Value::Map(fields) => fields,
+            _ => {
+                self.bad_document(parent_path, "a genre must be a name or a map");
+                return;
+            },
+        };
+
+        let field = |key: &str| fields.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v);
+
+        if let Some(link) = field("link") {
+            match (link.scalar(), fields.len()) {
+                (Some(path), 1) => self.references.push(Reference {
+                    parent,
+                    index: self.tree.children[parent].len(),
+                    path,
+                    file: self.file.clone(),
+                    line: 0,
+                    column: 0,
+                }),
+                _ => self.bad_document(parent_path, "a link must be a single path and nothing else"),
+            }
+            return;
+        }
+
+        let name = match value.scalar().or_else(|| field("name").and_then(|n| n.scalar())) {
+            Some(name) => name,
+            None => {
+                self.bad_document(parent_path, "a genre needs a \"name\"");
+                return;
+            },
+        };
+
+        let path = if parent_path.is_empty() {
+            name.clone()
+        } else {
+            format!("{} > {}", parent_path, name)
+        };
+
+        let aliases = match field("aliases").map(|a| a.strings()) {
+            None => Vec::new(),
+            Some(Some(aliases)) => aliases,
+            Some(None) => {
+                self.bad_document(&path, "\"aliases\" must be a string or a list of strings");
+                return;
+            },
+        };
+        let aliases: Vec<&str> = aliases.iter().map(|a| a.as_str()).collect();
+
+        let matchers = match parse_spellings(&name, &aliases) {
+            Ok(matchers) => matchers,
+            Err(kind) => {
+                self.error(&path, kind);
+                return;
+            },
+        };
+
+        if self.tree.children[parent].iter().any(|s| self.tree.name(*s) == name) {
+            self.error(&path, ParseErrorKind::DuplicateSibling(name));
+            return;
+        }
+
+        let style = self.tree.add_style(parent, &name);
+        self.tree.set_spellings(
+            style,
+            aliases.iter().map(|a| a.to_string()).collect(),
+            matchers,
+        );
+
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" | "aliases" => (),
+                "children" => self.load_children(value, style, &path),
+                key => match value.scalar() {
+                    Some(value) => {
+                        if let Err(kind) = set_attribute(self.tree, style, key, &value) {
+                            self.error(&path, kind);
+                        }
+                    },
+                    None => self.bad_document(&path, &format!("\"{}\" must be a string", key)),
+                },
+            }
+        }
+    }
+
+    fn bad_document(&mut self, path: &str, problem: &str) {
+        self.error(path, ParseErrorKind::BadDocument {
+            path: String::new(),
+            problem: problem.to_string(),
+        });
+    }
+
+    fn error(&mut self, path: &str, kind: ParseErrorKind) {
+        // Documents have no useful line numbers, so say where in the tree
+        let kind = match kind {
+            ParseErrorKind::BadDocument { problem, .. } => ParseErrorKind::BadDocument {
+                path: path.to_string(),
+                problem,
+            },
+            kind if path.is_empty() => kind,
+            kind => ParseErrorKind::BadDocument {
+                path: path.to_string(),
+                problem: kind.to_string(),
+            },
+        };
+
+        self.errors.push(ParseError {
+            file: self.file.clone(),
+            line: 0,
+            column: 0,
+            kind,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Match;
+    use super::*;
+
+    fn load_doc(source: &str, format: Format) -> Result<StyleTree, Vec<ParseError>> {
+        let mut tree = StyleTree::new();
+
+        match load(&mut tree, "test", source, format) {
+            Ok(()) => Ok(tree),
+            Err(LoadError::Parse(errors)) => Err(errors),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn errors(source: &str, format: Format) -> Vec<String> {
+        match load_doc(source, format) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    fn names(tree: &StyleTree, style: Style) -> Vec<&str> {
+        tree.children(style).iter().map(|s| tree.name(*s)).collect()
+    }
+
+    fn find(tree: &StyleTree, name: &str) -> Style {
+        tree.find_by_name(name)[0]
+    }
+
+    #[test]
+    fn json_fields_and_aliases() {
+        let tree = load_doc(r#"{
+            "match": "any",
+            "genres": [
+                {"name": "Electronic", "color": "cyan", "children": [
+                    {"name": "Drum & Bass", "aliases": ["DnB", "Drum and Bass"], "description": "Fast breaks", "display": "D&B"},
+                    {"name": "House", "aliases": "Haus"}
+                ]},
+                "Rock"
+            ]
+        }"#, Format::Json).unwrap();
+
+        assert!(tree.matching == Match::Any);
+        assert_eq!(names(&tree, tree.base()), ["Electronic", "Rock"]);
+
+        let electronic = find(&tree, "Electronic");
+        assert_eq!(tree.color(electronic), Some("cyan"));
+        assert_eq!(names(&tree, electronic), ["Drum & Bass", "House"]);
+
+        let dnb = find(&tree, "Drum & Bass");
+        assert_eq!(tree.aliases(dnb), ["DnB", "Drum and Bass"]);
+        assert_eq!(tree.description(dnb), Some("Fast breaks"));
+        assert_eq!(tree.display_name(dnb), "D&B");
+        assert_eq!(tree.aliases(find(&tree, "House")), ["Haus"]);
+    }
+
+    #[test]
+    fn yaml_links() {
+        let tree = load_doc("
+genres:
+  - name: Techno
+    children:
+      - link: House > Tech House
+      - Minimal
+  - name: House
+    children:
+      - Tech House
+", Format::Yaml).unwrap();
+
+        let techno = find(&tree, "Techno");
+        let tech_house = find(&tree, "Tech House");
+
+        assert_eq!(names(&tree, techno), ["Tech House", "Minimal"]);
+        assert!(tree.is_link(techno, tech_house));
+        assert_eq!(tree.path(tech_house), ["House", "Tech House"]);
+    }
+
+    #[test]
+    fn toml_keeps_child_order() {
+        let tree = load_doc(r#"
+sort = "file"
+
+[[genres]]
+name = "Zeta"
+
+[[genres.children]]
+name = "Omega"
+
+[[genres.children]]
+name = "Alpha"
+aliases = ["A"]
+
+[[genres.children]]
+link = "Beta"
+
+[[genres]]
+name = "Beta"
+"#, Format::Toml).unwrap();
+
+        let zeta = find(&tree, "Zeta");
+
+        assert_eq!(names(&tree, tree.base()), ["Zeta", "Beta"]);
+        assert_eq!(names(&tree, zeta), ["Omega", "Alpha", "Beta"]);
+        assert!(tree.is_link(zeta, find(&tree, "Beta")));
+    }
+
+    #[test]
+    fn syntax_errors_have_positions() {
+        assert_eq!(errors("[\n  \"House\",\n  ?\n]", Format::Json), ["test:3:3: unexpected character '?'"]);
+        assert!(errors("[[genres]]\nname = \n", Format::Toml)[0].starts_with("test:2:"));
+        assert!(errors("genres:\n  - [House\n", Format::Yaml)[0].starts_with("test:"));
+    }
+
+    #[test]
+    fn malformed_documents_say_where() {
+        assert_eq!(errors(r#""House""#, Format::Json), [
+            "test: expected a list of genres or a map with a \"genres\" list",
+        ]);
+        assert_eq!(errors(r#"[{"name": "House", "children": "Deep House"}]"#, Format::Json), [
+            "test: House: \"children\" must be a list",
+        ]);
+        assert_eq!(errors(r#"[{"name": "House", "children": [{"color": "red"}]}]"#, Format::Json), [
+            "test: House: a genre needs a \"name\"",
+        ]);
+        assert_eq!(errors(r#"[{"name": "House", "aliases": {"a": "b"}}]"#, Format::Json), [
+            "test: House: \"aliases\" must be a string or a list of strings",
+        ]);
+        assert_eq!(errors(r#"[{"link": "House", "name": "Techno"}]"#, Format::Json), [
+            "test: a link must be a single path and nothing else",
+        ]);
+        assert_eq!(errors(r#"["House", {"link": "Trance"}]"#, Format::Json), [
+            "test: \"@Trance\" does not refer to any genre",
+        ]);
+        assert_eq!(errors(r#"["House", "House"]"#, Format::Json), [
+            "test: House: \"House\" already exists under the same parent",
+        ]);
+    }
+}
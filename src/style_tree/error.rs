@@ -0,0 +1,217 @@
+use std::fmt;
+use std::io;
+
+use super::Indent;
+
+pub enum LoadError {
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    Parse(Vec<ParseError>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path, error),
+            LoadError::Parse(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Debug for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,      // 1-based, 0 if unknown
+    pub column: usize,    // 1-based
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            // Problems with the structure of a document have no position
+            write!(f, "{}: {}", self.file, self.kind)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.kind)
+        }
+    }
+}
+
+impl fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[derive(Clone)]
+pub enum ParseErrorKind {
+    TooDeep {
+        depth: usize,
+        max: usize,
+    },
+    MixedIndent,
+    InconsistentIndent {
+        expected: Indent,
+    },
+    UnevenIndent {
+        width: usize,
+        found: usize,
+    },
+    DuplicateSibling(String),
+    EmptyName,
+    BadQuotes,
+    ChildOfReference,
+    UnknownReference(String),
+    AmbiguousReference {
+        path: String,
+        count: usize,
+    },
+    ReferenceCycle(String),
+    ChildOfInclude,
+    IncludeFailed {
+        path: String,
+        reason: String,
+    },
+    IncludeCycle(String),
+    Syntax(String),
+    BadDocument {
+        path: String,   // Where in the document, e.g. "Electronic > House"
+        problem: String,
+    },
+    BadPattern {
+        pattern: String,
+        reason: String,
+    },
+    BadAttributes,
+    UnknownAttribute(String),
+    UnknownDirective(String),
+    BadValue {
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::TooDeep { depth, max } => write!(
+                f,
+                "indented to depth {}, but the deepest allowed here is {}",
+                depth,
+                max,
+            ),
+            ParseErrorKind::MixedIndent => write!(
+                f,
+                "indentation mixes tabs and spaces",
+            ),
+            ParseErrorKind::InconsistentIndent { expected } => write!(
+                f,
+                "indentation should use {}, like the rest of the file",
+                expected,
+            ),
+            ParseErrorKind::UnevenIndent { width, found } => write!(
+                f,
+                "indented by {} spaces, which is not a multiple of {}",
+                found,
+                width,
+            ),
+            ParseErrorKind::DuplicateSibling(name) => write!(
+                f,
+                "\"{}\" already exists under the same parent",
+                name,
+            ),
+            ParseErrorKind::EmptyName => write!(
+                f,
+                "a name or alias is empty",
+            ),
+            ParseErrorKind::BadQuotes => write!(
+                f,
+                "a quoted name is missing its closing quote or has more after it",
+            ),
+            ParseErrorKind::ChildOfReference => write!(
+                f,
+                "a reference can't have children of its own",
+            ),
+            ParseErrorKind::UnknownReference(path) => write!(
+                f,
+                "\"@{}\" does not refer to any genre",
+                path,
+            ),
+            ParseErrorKind::AmbiguousReference { path, count } => write!(
+                f,
+                "\"@{}\" could refer to {} genres, give more of its path",
+                path,
+                count,
+            ),
+            ParseErrorKind::ReferenceCycle(path) => write!(
+                f,
+                "\"@{}\" would make a genre its own ancestor",
+                path,
+            ),
+            ParseErrorKind::ChildOfInclude => write!(
+                f,
+                "an include can't have children, indent them in the included file",
+            ),
+            ParseErrorKind::IncludeFailed { path, reason } => write!(
+                f,
+                "could not include {}: {}",
+                path,
+                reason,
+            ),
+            ParseErrorKind::IncludeCycle(path) => write!(
+                f,
+                "{} ends up including itself",
+                path,
+            ),
+            ParseErrorKind::Syntax(message) => write!(f, "{}", message),
+            ParseErrorKind::BadDocument { path, problem } => {
+                if path.is_empty() {
+                    write!(f, "{}", problem)
+                } else {
+                    write!(f, "{}: {}", path, problem)
+                }
+            },
+            ParseErrorKind::BadPattern { pattern, reason } => write!(
+                f,
+                "invalid pattern \"{}\": {}",
+                pattern,
+                reason,
+            ),
+            ParseErrorKind::BadAttributes => write!(
+                f,
+                "malformed attribute list",
+            ),
+            ParseErrorKind::UnknownAttribute(key) => write!(
+                f,
+                "unknown attribute \"{}\"",
+                key,
+            ),
+            ParseErrorKind::UnknownDirective(key) => write!(
+                f,
+                "unknown directive \"%{}\"",
+                key,
+            ),
+            ParseErrorKind::BadValue { key, value } => write!(
+                f,
+                "\"{}\" is not a valid value for \"{}\"",
+                value,
+                key,
+            ),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use json::JsonValue;
+
+use super::parser::quote;
+use super::parser::quote_spelling;
+use super::Match;
+use super::Sort;
+use super::Style;
+use super::StyleTree;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Text,       // Canonical genre file
+    Json,
+    Dot,        // Graphviz
+    Markdown,   // Nested outline
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<ExportFormat> {
+        match name {
+            "text" | "txt" => Some(ExportFormat::Text),
+            "json" => Some(ExportFormat::Json),
+            "dot" => Some(ExportFormat::Dot),
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+impl StyleTree {
+    pub fn export(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Text => self.to_text(),
+            ExportFormat::Json => self.to_json(),
+            ExportFormat::Dot => self.to_dot(),
+            ExportFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    // Tab indented, with options first and attributes in a fixed order, so
+    // that two files describing the same tree come out identical
+    fn to_text(&self) -> String {
+        let mut out = String::new();
+
+        if self.matching != Match::Leaves {
+            out += &format!("%match {}\n", self.matching);
+        }
+        if !self.normalize.is_none() {
+            out += &format!("%normalize {}\n", self.normalize);
+        }
+        if !self.separators.is_empty() {
+            out += &format!("%separators {}\n", self.separators.join(" "));
+        }
+        if self.sorting != Sort::File {
+            out += &format!("%sort {}\n", self.sorting);
+        }
+
+        self.walk(self.base(), 0, &mut |parent, style, depth| {
+            out += &"\t".repeat(depth);
+
+            if self.is_link(parent, style) {
+                out += &format!("@{}\n", self.link_path(style));
+                return;
+            }
+
+            out += &quote_spelling(self.name(style));
+            for alias in self.aliases(style) {
+                out += &format!(" | {}", quote_spelling(alias));
+            }
+
+            let attributes = self.attributes(style);
+            if !attributes.is_empty() {
+                let attributes: Vec<String> = attributes.iter()
+                    .map(|(key, value)| match *key {
+                        "description" | "display" => format!("{}={}", key, quote(value)),
+                        _ => format!("{}={}", key, value),
+                    })
+                    .collect();
+                out += &format!(" {{{}}}", attributes.join(", "));
+            }
+
+            out += "\n";
+        });
+
+        out
+    }
+
+    // "Electronic > House", with names quoted where they'd be misread
+    fn link_path(&self, style: Style) -> String {
+        let names: Vec<String> = self.path(style).iter()
+            .map(|name| quote_spelling(name))
+            .collect();
+
+        names.join(" > ")
+    }
+
+    fn to_json(&self) -> String {
+        let mut root = JsonValue::new_object();
+
+        if self.matching != Match::Leaves {
+            root["match"] = self.matching.to_string().into();
+        }
+        if !self.normalize.is_none() {
+            root["normalize"] = self.normalize.to_string().into();
+        }
+        if !self.separators.is_empty() {
+            root["separators"] = self.separators.clone().into();
+        }
+        if self.sorting != Sort::File {
+            root["sort"] = self.sorting.to_string().into();
+        }
+
+        root["genres"] = self.json_children(self.base());
+
+        root.pretty(4) + "\n"
+    }
+
+    fn json_children(&self, parent: Style) -> JsonValue {
+        let mut children = JsonValue::new_array();
+
+        for &style in self.children(parent) {
+            let mut node = JsonValue::new_object();
+
+            if self.is_link(parent, style) {
+                node["link"] = self.link_path(style).into();
+                children.push(node).unwrap();
+                continue;
+            }
+
+            let attributes = self.attributes(style);
+            if self.aliases(style).is_empty()
+                && attributes.is_empty()
+                && self.children(style).is_empty()
+            {
+                // Plain leaves are just their name
+                children.push(self.name(style)).unwrap();
+                continue;
+            }
+
+            node["name"] = self.name(style).into();
+            if !self.aliases(style).is_empty() {
+                node["aliases"] = self.aliases(style).to_vec().into();
+            }
+            for (key, value) in attributes {
+                node[key] = value.into();
+            }
+            if !self.children(style).is_empty() {
+                node["children"] = self.json_children(style);
+            }
+
+            children.push(node).unwrap();
+        }
+
+        children
+    }
+
+    // Links drawn dashed
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph genres {\n");
+
+        for style in 1..self.names.len() {
+            out += &format!("\tn{} [label=\"{}\"];\n", style, dot_escape(self.name(style)));
+        }
+
+        self.walk(self.base(), 0, &mut |parent, style, _| {
+            if parent == self.base() {
+                return;
+            }
+
+            if self.is_link(parent, style) {
+                out += &format!("\tn{} -> n{} [style=dashed];\n", parent, style);
+            } else {
+                out += &format!("\tn{} -> n{};\n", parent, style);
+            }
+        });
+
+        out += "}\n";
+        out
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = String::new();
+
+        self.walk(self.base(), 0, &mut |parent, style, depth| {
+            out += &"  ".repeat(depth);
+
+            if self.is_link(parent, style) {
+                out += &format!("- {} (see {})\n", self.name(style), self.path(style).join(" > "));
+                return;
+            }
+
+            out += &format!("- **{}**", self.name(style));
+            if !self.aliases(style).is_empty() {
+                out += &format!(" (also {})", self.aliases(style).join(", "));
+            }
+            if let Some(description) = self.description(style) {
+                out += &format!(": {}", description);
+            }
+            out += "\n";
+        });
+
+        out
+    }
+
+    // Attributes that differ from the defaults
+    fn attributes(&self, style: Style) -> Vec<(&'static str, String)> {
+        let mut attributes = Vec::new();
+
+        if let Some(m) = self.node_matching[style] {
+            attributes.push(("match", m.to_string()));
+        }
+        if let Some(color) = self.color(style) {
+            attributes.push(("color", color.to_string()));
+        }
+        if let Some(display) = &self.display_names[style] {
+            attributes.push(("display", display.clone()));
+        }
+        if let Some(sort) = self.node_sorting[style] {
+            attributes.push(("sort", sort.to_string()));
+        }
+        if let Some(pin) = self.pin(style) {
+            attributes.push(("pin", pin.to_string()));
+        }
+        if let Some(description) = self.description(style) {
+            attributes.push(("description", description.to_string()));
+        }
+
+        attributes
+    }
+
+    // Calls f with (parent, style, depth) for every style in file order.
+    // Linked styles are visited but not descended into.
+    fn walk<F: FnMut(Style, Style, usize)>(&self, parent: Style, depth: usize, f: &mut F) {
+        for &style in self.children(parent) {
+            f(parent, style, depth);
+
+            if !self.is_link(parent, style) {
+                self.walk(style, depth + 1, f);
+            }
+        }
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::document;
+    use super::super::parse_text;
+    use super::super::Format;
+    use super::*;
+
+    #[test]
+    fn text_export_round_trips_awkward_names() {
+        let mut tree = StyleTree::new();
+        let source = r#"[
+            "Rock #1",
+            {"name": "Pop | Rock", "aliases": ["a \"b\""], "description": "say \"hi\" \\o/"},
+            "%weird",
+            "@home",
+            "R&B {live}",
+            {"name": "A > B", "children": ["12\" Mix", {"link": "\"R&B {live}\""}]},
+            "  padded "
+        ]"#;
+        document::load(&mut tree, "test.json", source, Format::Json).unwrap();
+
+        let text = tree.export(ExportFormat::Text);
+        let reloaded = parse_text(&text).unwrap();
+
+        assert_eq!(reloaded.export(ExportFormat::Text), text);
+        assert_eq!(reloaded.export(ExportFormat::Json), tree.export(ExportFormat::Json));
+
+        let names: Vec<&str> = reloaded.children(reloaded.base()).iter()
+            .map(|s| reloaded.name(*s))
+            .collect();
+        assert_eq!(names, ["Rock #1", "Pop | Rock", "%weird", "@home", "R&B {live}", "A > B", "  padded "]);
+    }
+
+    #[test]
+    fn only_awkward_names_are_quoted() {
+        let text = concat!(
+            "Electronic {description=\"Mostly synths\"}\n",
+            "\tC# | Drum and Bass\n",
+            "\t\"12\\\" Mix\"\n",
+            "Other\n",
+            "\t@Electronic > C#\n",
+        );
+        let tree = parse_text("Electronic {description=\"Mostly synths\"}\n\tC# | Drum and Bass\n\t12\" Mix\nOther\n\t@Electronic > C#\n").unwrap();
+
+        assert_eq!(tree.export(ExportFormat::Text), text);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert!(parse_text("\"Rock\n").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::Style;
+use super::StyleTree;
+
+pub enum Problem {
+    RepeatedName {
+        name: String,
+        paths: Vec<String>,
+    },
+    DeadLeaf(String),       // Matches no songs in the library
+    EmptySubtree {          // Nothing underneath matches any songs
+        path: String,
+        leaves: usize,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::RepeatedName { name, paths } => {
+                write!(f, "'{}' appears {} times: {}", name, paths.len(), paths.join(", "))
+            },
+            Problem::DeadLeaf(path) => write!(f, "{} matches no songs", path),
+            Problem::EmptySubtree { path, leaves } => {
+                write!(f, "none of the {} genres under {} match any songs", leaves, path)
+            },
+        }
+    }
+}
+
+impl StyleTree {
+    // Problems worth fixing in a tree that loaded fine. Duplicate siblings
+    // are already load errors. Dead leaves and empty subtrees can only be
+    // found given the genre tags in the library, after resolve().
+    //
+    // Every name defined more than once is reported. With cross_branch_only,
+    // only names that pick up tags in separate branches are: a genre
+    // repeated as its own child, as in Dubstep > Dubstep, is one placement,
+    // and groupings such as Fusion pick up no tags of their own.
+    pub fn check(&self, genres: Option<&[String]>, cross_branch_only: bool) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        let mut checked = HashSet::new();
+        for style in 1..self.names.len() {
+            let name = self.name(style);
+            if !checked.insert(name) {
+                continue;
+            }
+
+            let mut placements: Vec<Style> = Vec::new();
+            for &s in self.find_by_name(name) {
+                let placement = if !cross_branch_only {
+                    s
+                } else if self.matches_own_tags(s) {
+                    self.top_of_repeats(s)
+                } else {
+                    continue;
+                };
+
+                if !placements.contains(&placement) {
+                    placements.push(placement);
+                }
+            }
+
+            if placements.len() > 1 {
+                // Shallowest first, as the one to keep
+                placements.sort_by_key(|s| self.depth(*s));
+
+                problems.push(Problem::RepeatedName {
+                    name: name.to_string(),
+                    paths: placements.iter().map(|s| self.path(*s).join(" > ")).collect(),
+                });
+            }
+        }
+
+        if let Some(genres) = genres {
+            let genres: HashSet<&str> = genres.iter().map(|g| g.as_str()).collect();
+            self.check_empty(self.base(), &genres, &mut problems);
+        }
+
+        problems
+    }
+
+    // The outermost of a genre and the children repeating its name
+    fn top_of_repeats(&self, style: Style) -> Style {
+        let mut top = style;
+
+        while let Some(parent) = self.parent(top) {
+            if self.name(parent) != self.name(style) {
+                break;
+            }
+            top = parent;
+        }
+
+        top
+    }
+
+    // Reports the highest empty style on each branch rather than
+    // everything under it
+    fn check_empty(&self, parent: Style, genres: &HashSet<&str>, problems: &mut Vec<Problem>) {
+        for &style in self.children(parent) {
+            // Linked styles are checked where they are defined
+            if self.is_link(parent, style) {
+                continue;
+            }
+
+            let empty = self.tags(style).iter().all(|t| !genres.contains(t));
+            let path = self.path(style).join(" > ");
+
+            if empty && self.children(style).is_empty() {
+                problems.push(Problem::DeadLeaf(path));
+            } else if empty {
+                problems.push(Problem::EmptySubtree {
+                    path,
+                    leaves: self.leaves_under(style).len(),
+                });
+            } else {
+                self.check_empty(style, genres, problems);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_text;
+    use super::*;
+
+    fn repeated(text: &str, cross_branch_only: bool) -> Vec<String> {
+        let tree = parse_text(text).unwrap();
+
+        tree.check(None, cross_branch_only).into_iter()
+            .filter_map(|p| match p {
+                Problem::RepeatedName { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_repeated_name_is_reported() {
+        assert_eq!(repeated("Electronic\n\tDubstep\n\t\tDubstep\n\t\tBrostep\n", false), ["Dubstep"]);
+        assert_eq!(repeated("House\n\tFusion\n\t\tA\nTechno\n\tFusion\n\t\tB\n", false), ["Fusion"]);
+    }
+
+    #[test]
+    fn genre_repeated_as_its_own_child_can_be_allowed() {
+        assert!(repeated("Electronic\n\tDubstep\n\t\tDubstep\n\t\t\tDubstep\n\t\tBrostep\n", true).is_empty());
+    }
+
+    #[test]
+    fn groupings_without_tags_can_be_allowed() {
+        assert!(repeated("House\n\tFusion\n\t\tA\nTechno\n\tFusion\n\t\tB\n", true).is_empty());
+    }
+
+    #[test]
+    fn links_are_fine() {
+        let text = "House\n\tFusion\n\t\tTech House\nTechno\n\tFusion\n\t\t@House > Fusion > Tech House\n";
+        assert_eq!(repeated(text, false), ["Fusion"]);
+        assert!(repeated(text, true).is_empty());
+    }
+
+    #[test]
+    fn genre_in_two_branches_is_reported() {
+        assert_eq!(repeated("House\n\tTech House\nTechno\n\tTech House\n", false), ["Tech House"]);
+        assert_eq!(repeated("House\n\tTech House\nTechno\n\tTech House\n", true), ["Tech House"]);
+        assert_eq!(repeated("House\n\tDubstep\nDubstep\n\tDubstep\n", true), ["Dubstep"]);
+    }
+
+    #[test]
+    fn grouping_matching_any_counts() {
+        assert_eq!(repeated("%match any\nHouse\n\tFusion\n\t\tA\nTechno\n\tFusion\n\t\tB\n", true), ["Fusion"]);
+    }
+}
@@ -0,0 +1,35 @@
+use glob::Pattern;
+use regex::Regex;
+
+// One spelling of a genre: either a literal tag, or a pattern that is
+// resolved against the genre tags the server actually has
+pub enum Matcher {
+    Exact(String),
+    Regex(Regex),
+    Glob(Pattern),
+}
+
+impl Matcher {
+    // "re:<regex>" and "glob:<pattern>" are patterns, anything else is exact
+    pub fn parse(spelling: &str) -> Result<Matcher, String> {
+        if let Some(re) = spelling.strip_prefix("re:") {
+            Regex::new(re)
+                .map(Matcher::Regex)
+                .map_err(|e| e.to_string())
+        } else if let Some(glob) = spelling.strip_prefix("glob:") {
+            Pattern::new(glob)
+                .map(Matcher::Glob)
+                .map_err(|e| e.to_string())
+        } else {
+            Ok(Matcher::Exact(spelling.to_string()))
+        }
+    }
+
+    pub fn is_match(&self, tag: &str) -> bool {
+        match self {
+            Matcher::Exact(s) => s == tag,
+            Matcher::Regex(re) => re.is_match(tag),
+            Matcher::Glob(glob) => glob.matches(tag),
+        }
+    }
+}
@@ -0,0 +1,655 @@
+pub use error::LoadError;
+pub use error::ParseError;
+pub use error::ParseErrorKind;
+pub use export::ExportFormat;
+pub use source::Entry;
+pub use source::Source;
+
+mod bootstrap;
+mod document;
+mod error;
+mod export;
+mod lint;
+mod matcher;
+mod normalize;
+mod parser;
+mod source;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use matcher::Matcher;
+use normalize::Normalize;
+use parser::Parser;
+
+pub type Style = usize;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Indent::Tabs => write!(f, "tabs"),
+            Indent::Spaces(1) => write!(f, "1 space"),
+            Indent::Spaces(n) => write!(f, "{} spaces", n),
+        }
+    }
+}
+
+// Colors a style can be given with the color attribute
+pub const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text" | "txt" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    // Anything without a recognised extension is read as text
+    pub fn from_path(path: &str) -> Format {
+        Path::new(path).extension()
+            .and_then(|e| e.to_str())
+            .and_then(Format::parse)
+            .unwrap_or(Format::Text)
+    }
+}
+
+// Which node names a track's genre tag is compared against
+#[derive(Clone, Copy, PartialEq)]
+pub enum Match {
+    Leaves,     // Only the lowest level subgenres
+    Any,        // Every node, leaf or not
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Match::Leaves => write!(f, "leaves"),
+            Match::Any => write!(f, "any"),
+        }
+    }
+}
+
+// Order styles are listed in menus
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sort {
+    File,       // As they appear in the genre file
+    Alpha,      // Alphabetically by display name
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sort::File => write!(f, "file"),
+            Sort::Alpha => write!(f, "alpha"),
+        }
+    }
+}
+
+// Keeps a style at one end of its menu whatever the sort order
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pin {
+    Top,
+    Bottom,
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pin::Top => write!(f, "top"),
+            Pin::Bottom => write!(f, "bottom"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    // Detected from the first indented line when None
+    pub indent: Option<Indent>,
+    // Guessed from the file extension when None
+    pub format: Option<Format>,
+}
+
+// Base of tree is at index 0
+pub struct StyleTree {
+    names: Vec<String>,
+    aliases: Vec<Vec<String>>,     // Alternate spellings of the genre tag
+    matchers: Vec<Vec<Matcher>>,   // Name followed by aliases
+    resolved: Vec<Vec<String>>,    // Actual genre tags the matchers stand for
+    parents: Vec<Option<Style>>,   // Where each style is defined
+    children: Vec<Vec<Style>>,     // Includes styles linked in with @
+    by_name: HashMap<String, Vec<Style>>,
+    colors: Vec<Option<String>>,
+    descriptions: Vec<Option<String>>,
+    display_names: Vec<Option<String>>,    // Shown in menus instead of the name
+    pins: Vec<Option<Pin>>,
+
+    normalize: Normalize,
+    separators: Vec<String>,    // Split one genre tag into several
+    matching: Match,
+    node_matching: Vec<Option<Match>>,  // Overrides inherited by subtrees
+    sorting: Sort,
+    node_sorting: Vec<Option<Sort>>,
+
+    files: Vec<String>,     // Read to build the tree, includes after the first
+}
+
+impl StyleTree {
+    fn new() -> StyleTree {
+        StyleTree {
+            names: vec!["Root".to_string()],
+            aliases: vec![Vec::new()],
+            matchers: vec![Vec::new()],
+            resolved: vec![Vec::new()],
+            parents: vec![None],
+            children: vec![Vec::new()],
+            by_name: HashMap::new(),
+            colors: vec![None],
+            descriptions: vec![None],
+            display_names: vec![None],
+            pins: vec![None],
+            normalize: Normalize::default(),
+            separators: Vec::new(),
+            matching: Match::Leaves,
+            node_matching: vec![None],
+            sorting: Sort::File,
+            node_sorting: vec![None],
+            files: Vec::new(),
+        }
+    }
+
+    fn add_style(&mut self, parent: Style, name: &str) -> Style {
+        let style = self.names.len();
+
+        self.names.push(name.to_string());
+        self.aliases.push(Vec::new());
+        self.matchers.push(Vec::new());
+        self.resolved.push(Vec::new());
+        self.parents.push(Some(parent));
+        self.children.push(Vec::new());
+        self.children[parent].push(style);
+        self.by_name.entry(name.to_string()).or_default().push(style);
+        self.colors.push(None);
+        self.descriptions.push(None);
+        self.display_names.push(None);
+        self.pins.push(None);
+        self.node_matching.push(None);
+        self.node_sorting.push(None);
+
+        style
+    }
+
+    fn set_spellings(&mut self, style: Style, aliases: Vec<String>, matchers: Vec<Matcher>) {
+        // Until resolve() is called only the exact spellings are known
+        self.resolved[style] = matchers.iter()
+            .filter_map(|m| match m {
+                Matcher::Exact(tag) => Some(tag.clone()),
+                _ => None,
+            }).collect();
+
+        self.aliases[style] = aliases;
+        self.matchers[style] = matchers;
+    }
+
+    // Work out which of the genre tags present in the library each style
+    // stands for. Tags holding several genres are kept whole, so they can
+    // still be used to query MPD.
+    pub fn resolve(&mut self, genres: &[String]) {
+        let split: Vec<Vec<&str>> = genres.iter()
+            .map(|g| self.split_genres(g))
+            .collect();
+
+        // Each part of each tag is normalized once, so exact spellings can
+        // be looked up rather than compared against the whole library
+        let mut by_spelling: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, parts) in split.iter().enumerate() {
+            for part in parts {
+                let found = by_spelling.entry(self.normalize.apply(part)).or_default();
+                if found.last() != Some(&i) {
+                    found.push(i);
+                }
+            }
+        }
+
+        for style in 0..self.names.len() {
+            let mut resolved: Vec<String> = Vec::new();
+
+            for matcher in &self.matchers[style] {
+                let found: Vec<usize> = match matcher {
+                    Matcher::Exact(tag) => {
+                        if !resolved.contains(tag) {
+                            resolved.push(tag.clone());
+                        }
+                        by_spelling.get(&self.normalize.apply(tag)).cloned().unwrap_or_default()
+                    },
+                    _ => (0..genres.len())
+                        .filter(|i| split[*i].iter().any(|part| matcher.is_match(part)))
+                        .collect(),
+                };
+
+                for i in found {
+                    if !resolved.contains(&genres[i]) {
+                        resolved.push(genres[i].clone());
+                    }
+                }
+            }
+
+            self.resolved[style] = resolved;
+        }
+    }
+
+    fn spelled_by(&self, matcher: &Matcher, genre: &str) -> bool {
+        match matcher {
+            // Pick up spellings that only differ after normalizing
+            Matcher::Exact(tag) => self.normalize.apply(genre) == self.normalize.apply(tag),
+            _ => matcher.is_match(genre),
+        }
+    }
+
+    // Styles a genre tag falls under directly. Works before resolve() is
+    // called, as the tag is checked against the patterns themselves.
+    pub fn find_tag(&self, tag: &str) -> Vec<Style> {
+        let parts = self.split_genres(tag);
+
+        (1..self.names.len())
+            .filter(|s| {
+                self.resolved[*s].iter().any(|t| t == tag)
+                    || self.matchers[*s].iter().any(|m| parts.iter().any(|p| self.spelled_by(m, p)))
+            })
+            .collect()
+    }
+
+    // Every way down from the top of the tree to style, links included
+    pub fn paths_to(&self, style: Style) -> Vec<Vec<Style>> {
+        let mut paths = Vec::new();
+        self.collect_paths(self.base(), style, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn collect_paths(&self, from: Style, to: Style, path: &mut Vec<Style>, paths: &mut Vec<Vec<Style>>) {
+        for &child in &self.children[from] {
+            path.push(child);
+
+            if child == to {
+                paths.push(path.clone());
+            } else {
+                self.collect_paths(child, to, path, paths);
+            }
+
+            path.pop();
+        }
+    }
+
+    // Paths of every style a genre tag falls under directly, as names
+    pub fn paths_for_tag(&self, tag: &str) -> Vec<Vec<&str>> {
+        self.find_tag(tag).into_iter()
+            .flat_map(|style| self.paths_to(style))
+            .map(|path| path.into_iter().map(|s| self.name(s)).collect())
+            .collect()
+    }
+
+    // Splits a genre tag holding several genres, e.g. "Trip-Hop; Downtempo"
+    fn split_genres<'a>(&self, tag: &'a str) -> Vec<&'a str> {
+        let mut parts = vec![tag];
+
+        for separator in &self.separators {
+            parts = parts.iter()
+                .flat_map(|p| p.split(separator.as_str()))
+                .collect();
+        }
+
+        parts.into_iter()
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+
+    pub fn base(&self) -> Style {
+        0
+    }
+
+    pub fn name(&self, style: Style) -> &str {
+        &self.names[style]
+    }
+
+    pub fn aliases(&self, style: Style) -> &[String] {
+        &self.aliases[style]
+    }
+
+    pub fn color(&self, style: Style) -> Option<&str> {
+        self.colors[style].as_deref()
+    }
+
+    pub fn description(&self, style: Style) -> Option<&str> {
+        self.descriptions[style].as_deref()
+    }
+
+    pub fn display_name(&self, style: Style) -> &str {
+        self.display_names[style].as_deref().unwrap_or(&self.names[style])
+    }
+
+    pub fn pin(&self, style: Style) -> Option<Pin> {
+        self.pins[style]
+    }
+
+    pub fn children(&self, style: Style) -> &[Style] {
+        &self.children[style]
+    }
+
+    // Where style is defined. None for the base.
+    pub fn parent(&self, style: Style) -> Option<Style> {
+        self.parents[style]
+    }
+
+    // Parent, grandparent and so on, up to but not including the base
+    pub fn ancestors(&self, style: Style) -> Vec<Style> {
+        let mut ancestors = Vec::new();
+        let mut current = self.parents[style];
+
+        while let Some(s) = current {
+            if s == self.base() {
+                break;
+            }
+            ancestors.push(s);
+            current = self.parents[s];
+        }
+
+        ancestors
+    }
+
+    // Everything below style, following links, each style listed once
+    pub fn descendants(&self, style: Style) -> Vec<Style> {
+        let mut descendants = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<Style> = self.children[style].iter().rev().cloned().collect();
+
+        while let Some(s) = stack.pop() {
+            if !seen.insert(s) {
+                continue;
+            }
+            descendants.push(s);
+            stack.extend(self.children[s].iter().rev());
+        }
+
+        descendants
+    }
+
+    // Styles under style that have no children of their own
+    pub fn leaves_under(&self, style: Style) -> Vec<Style> {
+        self.descendants(style).into_iter()
+            .filter(|s| self.children[*s].is_empty())
+            .collect()
+    }
+
+    // Top level styles are at depth 1
+    pub fn depth(&self, style: Style) -> usize {
+        let mut depth = 0;
+        let mut current = self.parents[style];
+
+        while let Some(s) = current {
+            depth += 1;
+            current = self.parents[s];
+        }
+
+        depth
+    }
+
+    // Styles with the given name, in file order. Names only have to be
+    // unique among siblings, so there may be several.
+    pub fn find_by_name(&self, name: &str) -> &[Style] {
+        self.by_name.get(name).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    // Whether child was linked under parent rather than defined there
+    pub fn is_link(&self, parent: Style, child: Style) -> bool {
+        self.parents[child] != Some(parent)
+    }
+
+    // Names from the top of the tree down to style, along where it is defined
+    pub fn path(&self, style: Style) -> Vec<&str> {
+        if style == self.base() {
+            return Vec::new();
+        }
+
+        let mut path: Vec<&str> = self.ancestors(style).iter().rev()
+            .map(|s| self.name(*s))
+            .collect();
+        path.push(self.name(style));
+        path
+    }
+
+    // Styles whose path ends with the given names, e.g. ["Dubstep", "Brostep"]
+    fn find_path(&self, names: &[&str]) -> Vec<Style> {
+        let last = match names.last() {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+
+        self.find_by_name(last).iter()
+            .filter(|s| self.path(**s).ends_with(names))
+            .cloned()
+            .collect()
+    }
+
+    fn reaches(&self, from: Style, to: Style) -> bool {
+        let mut stack = vec![from];
+
+        while let Some(s) = stack.pop() {
+            if s == to {
+                return true;
+            }
+            stack.extend(&self.children[s]);
+        }
+
+        false
+    }
+
+    // How the tags of style are matched, taking overrides on its ancestors
+    // into account
+    pub fn matching(&self, style: Style) -> Match {
+        let mut current = Some(style);
+
+        while let Some(s) = current {
+            if let Some(m) = self.node_matching[s] {
+                return m;
+            }
+            current = self.parents[s];
+        }
+
+        self.matching
+    }
+
+    // How the children of style are ordered, taking overrides on its
+    // ancestors into account
+    pub fn sorting(&self, style: Style) -> Sort {
+        let mut current = Some(style);
+
+        while let Some(s) = current {
+            if let Some(sort) = self.node_sorting[s] {
+                return sort;
+            }
+            current = self.parents[s];
+        }
+
+        self.sorting
+    }
+
+    // Orders the children of parents for a menu. Children of several parents
+    // are only sorted alphabetically if all of the parents ask for it.
+    pub fn sort_menu(&self, parents: &[Style], styles: &mut [Style]) {
+        let alpha = !parents.is_empty()
+            && parents.iter().all(|p| self.sorting(*p) == Sort::Alpha);
+
+        styles.sort_by(|a, b| {
+            let rank = |s: Style| match self.pin(s) {
+                Some(Pin::Top) => 0,
+                None => 1,
+                Some(Pin::Bottom) => 2,
+            };
+
+            let order = rank(*a).cmp(&rank(*b));
+            if alpha {
+                order.then_with(|| {
+                    self.display_name(*a).to_lowercase().cmp(&self.display_name(*b).to_lowercase())
+                })
+            } else {
+                order
+            }
+        });
+    }
+
+    // Genre tags of all tracks that fall under style
+    pub fn tags(&self, style: Style) -> Vec<&str> {
+        let mut tags = Vec::new();
+
+        for s in std::iter::once(style).chain(self.descendants(style)) {
+            if s != self.base() && self.matches_own_tags(s) {
+                tags.extend(self.resolved[s].iter().map(|t| t.as_str()));
+            }
+        }
+
+        tags
+    }
+
+    // Leaves always pick up their own tags, other styles only when matching
+    // any
+    fn matches_own_tags(&self, style: Style) -> bool {
+        self.children[style].is_empty() || self.matching(style) == Match::Any
+    }
+
+    // Genre tags in the library that no style picks up, so tracks tagged
+    // with them can't be reached from the tree
+    pub fn unmapped<'a>(&self, genres: &'a [String]) -> Vec<&'a str> {
+        let mapped: HashSet<&str> = self.tags(self.base()).into_iter().collect();
+
+        genres.iter()
+            .map(|g| g.as_str())
+            .filter(|g| !mapped.contains(g))
+            .collect()
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn load(path: &str, options: &LoadOptions) -> Result<StyleTree, LoadError> {
+        let mut tree = StyleTree::new();
+
+        let format = options.format.unwrap_or_else(|| Format::from_path(path));
+        tree.files.push(path.to_string());
+
+        if format == Format::Text {
+            let file = File::open(path).map_err(|error| LoadError::Io {
+                path: path.to_string(),
+                error,
+            })?;
+
+            Parser::new(&mut tree, path, options).parse(BufReader::new(file))?;
+        } else {
+            let source = fs::read_to_string(path).map_err(|error| LoadError::Io {
+                path: path.to_string(),
+                error,
+            })?;
+
+            document::load(&mut tree, path, &source, format)?;
+        }
+
+        Ok(tree)
+    }
+}
+
+// A tree read from genre file text, for the tests of every module
+#[cfg(test)]
+fn parse_text(text: &str) -> Result<StyleTree, LoadError> {
+    let mut tree = StyleTree::new();
+    Parser::new(&mut tree, "test.txt", &LoadOptions::default()).parse(text.as_bytes())?;
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(text: &str) -> StyleTree {
+        parse_text(text).unwrap()
+    }
+
+    fn names(tree: &StyleTree, styles: &[Style]) -> Vec<String> {
+        styles.iter().map(|s| tree.path(*s).join(" > ")).collect()
+    }
+
+    #[test]
+    fn leaves_under_follows_links() {
+        let tree = tree("House\n\tDeep House\n\tFusion\n\t\tTech House\nTechno\n\tMinimal\n\t@House > Fusion\n");
+        let house = tree.find_by_name("House")[0];
+        let techno = tree.find_by_name("Techno")[0];
+
+        assert_eq!(names(&tree, &tree.leaves_under(house)), ["House > Deep House", "House > Fusion > Tech House"]);
+        assert_eq!(names(&tree, &tree.leaves_under(techno)), ["Techno > Minimal", "House > Fusion > Tech House"]);
+        assert!(tree.leaves_under(tree.find_by_name("Minimal")[0]).is_empty());
+    }
+
+    #[test]
+    fn depth_counts_from_the_top_level() {
+        let tree = tree("House\n\tFusion\n\t\tTech House\nTechno\n\t@House > Fusion\n");
+
+        assert_eq!(tree.depth(tree.base()), 0);
+        assert_eq!(tree.depth(tree.find_by_name("House")[0]), 1);
+        assert_eq!(tree.depth(tree.find_by_name("Fusion")[0]), 2);
+        // Where a style is defined, not where it is linked
+        assert_eq!(tree.depth(tree.find_by_name("Tech House")[0]), 3);
+    }
+
+    #[test]
+    fn resolve_matches_normalized_and_split_tags() {
+        let mut tree = tree(concat!(
+            "%normalize all\n",
+            "%separators ; /\n",
+            "Trip-Hop | trip hop\n",
+            "Drum & Bass | \"re:^D(rum)? ?(&|n|and) ?B(ass)?$\"\n",
+            "Rock\n",
+        ));
+        let library: Vec<String> = ["trip-hop ", "TRIP HOP", "Rock; Trip-Hop", "DnB", "Drum and Bass", "Pop", "rock"]
+            .iter().map(|g| g.to_string()).collect();
+        tree.resolve(&library);
+
+        let resolved = |name: &str| tree.resolved[tree.find_by_name(name)[0]].clone();
+        assert_eq!(resolved("Trip-Hop"), ["Trip-Hop", "trip-hop ", "TRIP HOP", "Rock; Trip-Hop", "trip hop"]);
+        assert_eq!(resolved("Drum & Bass"), ["Drum & Bass", "DnB", "Drum and Bass"]);
+        assert_eq!(resolved("Rock"), ["Rock", "Rock; Trip-Hop", "rock"]);
+    }
+
+    #[test]
+    fn ancestors_and_path_stop_at_the_base() {
+        let tree = tree("House\n\tFusion\n\t\tTech House\n");
+        let tech_house = tree.find_by_name("Tech House")[0];
+
+        assert_eq!(names(&tree, &tree.ancestors(tech_house)), ["House > Fusion", "House"]);
+        assert_eq!(tree.path(tech_house), ["House", "Fusion", "Tech House"]);
+        assert!(tree.path(tree.base()).is_empty());
+    }
+}
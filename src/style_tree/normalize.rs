@@ -0,0 +1,92 @@
+use std::fmt;
+
+use unicode_normalization::UnicodeNormalization;
+
+// How genre tags are folded before they are compared, so that e.g.
+// "trip-hop " and "Trip-Hop" can be treated as the same genre
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Normalize {
+    pub case: bool,       // Compare case-insensitively
+    pub unicode: bool,    // Compare by NFKC form
+    pub space: bool,      // Trim and collapse runs of whitespace
+    pub punct: bool,      // Treat punctuation as whitespace
+}
+
+impl Normalize {
+    pub fn is_none(&self) -> bool {
+        *self == Normalize::default()
+    }
+
+    // Parses a space separated list such as "case space", or "all"/"none"
+    pub fn parse(value: &str) -> Option<Normalize> {
+        let mut normalize = Normalize::default();
+
+        for word in value.split_whitespace() {
+            match word {
+                "case" => normalize.case = true,
+                "unicode" => normalize.unicode = true,
+                "space" => normalize.space = true,
+                "punct" => normalize.punct = true,
+                "all" => normalize = Normalize {
+                    case: true,
+                    unicode: true,
+                    space: true,
+                    punct: true,
+                },
+                "none" => normalize = Normalize::default(),
+                _ => return None,
+            }
+        }
+
+        Some(normalize)
+    }
+
+    pub fn apply(&self, tag: &str) -> String {
+        let mut tag: String = if self.unicode {
+            tag.nfkc().collect()
+        } else {
+            tag.to_string()
+        };
+
+        if self.case {
+            tag = tag.to_lowercase();
+        }
+
+        if self.punct {
+            tag = tag.chars()
+                .map(|c| if c.is_ascii_punctuation() || is_unicode_punct(c) { ' ' } else { c })
+                .collect();
+        }
+
+        if self.space || self.punct {
+            tag = tag.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+
+        tag
+    }
+}
+
+fn is_unicode_punct(c: char) -> bool {
+    matches!(c, '\u{2010}'..='\u{2027}' | '\u{2030}'..='\u{205E}' | '\u{3001}'..='\u{3003}')
+}
+
+// The inverse of Normalize::parse
+impl fmt::Display for Normalize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<&str> = [
+            (self.case, "case"),
+            (self.unicode, "unicode"),
+            (self.space, "space"),
+            (self.punct, "punct"),
+        ].iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, word)| *word)
+            .collect();
+
+        if words.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", words.join(" "))
+        }
+    }
+}
@@ -0,0 +1,962 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::Indent;
+use super::LoadError;
+use super::LoadOptions;
+use super::Match;
+use super::Matcher;
+use super::Normalize;
+use super::ParseError;
+use super::ParseErrorKind;
+use super::Pin;
+use super::Sort;
+use super::Style;
+use super::StyleTree;
+use super::COLORS;
+
+// An "@path" line, linked in once the whole file has been read so that it
+// can point further down the file
+pub struct Reference {
+    pub parent: Style,
+    pub index: usize,   // Position among the parent's children
+    pub path: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct Parser<'a> {
+    tree: &'a mut StyleTree,
+    forced_indent: Option<Indent>,
+    siblings: HashMap<Style, HashSet<String>>,
+    references: Vec<Reference>,
+    including: Vec<PathBuf>,   // Files currently being read, outermost first
+    errors: Vec<ParseError>,
+
+    // State of the file currently being read
+    file: String,
+    base: Style,    // Parent of the file's top level styles
+    indent: Option<Indent>,
+    stack: Vec<Style>,
+    childless: Option<(usize, ParseErrorKind)>,   // Depth of the last line if it can't have children
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tree: &'a mut StyleTree, file: &str, options: &LoadOptions) -> Parser<'a> {
+        let base = tree.base();
+
+        Parser {
+            tree,
+            forced_indent: options.indent,
+            siblings: HashMap::new(),
+            references: Vec::new(),
+            including: fs::canonicalize(file).into_iter().collect(),
+            errors: Vec::new(),
+            file: file.to_string(),
+            base,
+            indent: options.indent,
+            stack: Vec::new(),
+            childless: None,
+        }
+    }
+
+    pub fn parse<R: BufRead>(mut self, reader: R) -> Result<(), LoadError> {
+        self.read(reader).map_err(|error| LoadError::Io {
+            path: self.file.clone(),
+            error,
+        })?;
+
+        self.link_references();
+        self.errors.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(LoadError::Parse(self.errors))
+        }
+    }
+
+    fn read<R: BufRead>(&mut self, reader: R) -> Result<(), io::Error> {
+        for (index, line) in reader.lines().enumerate() {
+            self.parse_line(index + 1, &line?);
+        }
+
+        Ok(())
+    }
+
+    // Reads another genre file in place of an include line, with its top
+    // level styles becoming children of the line's parent
+    fn include(&mut self, line_no: usize, column: usize, depth: usize, target: &str) {
+        let parent = self.parent_at(line_no, column, depth);
+
+        let path = match Path::new(&self.file).parent() {
+            Some(dir) => dir.join(target),
+            None => PathBuf::from(target),
+        };
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                self.error(line_no, column, ParseErrorKind::IncludeFailed {
+                    path: path.display().to_string(),
+                    reason: e.to_string(),
+                });
+                return;
+            },
+        };
+
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.including.contains(&canonical) {
+            self.error(
+                line_no,
+                column,
+                ParseErrorKind::IncludeCycle(path.display().to_string()),
+            );
+            return;
+        }
+
+        self.including.push(canonical);
+        self.tree.files.push(path.display().to_string());
+        let file_name = std::mem::replace(&mut self.file, path.display().to_string());
+        let base = std::mem::replace(&mut self.base, parent);
+        let indent = std::mem::replace(&mut self.indent, self.forced_indent);
+        let stack = std::mem::take(&mut self.stack);
+        let childless = self.childless.take();
+
+        let result = self.read(BufReader::new(file));
+
+        self.file = file_name;
+        self.base = base;
+        self.indent = indent;
+        self.stack = stack;
+        self.childless = childless;
+        self.including.pop();
+
+        if let Err(e) = result {
+            self.error(line_no, column, ParseErrorKind::IncludeFailed {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            });
+        }
+    }
+
+    fn parse_line(&mut self, line_no: usize, line: &str) {
+        let line = strip_comment(line).trim_end();
+
+        // Blank and comment-only lines carry no genre
+        if line.trim_start().is_empty() {
+            return;
+        }
+
+        let (depth, name_start) = match self.measure_indent(line_no, line) {
+            Some(indent) => indent,
+            None => return,
+        };
+
+        let content = &line[name_start..];
+
+        if let Some((childless_depth, kind)) = &self.childless {
+            if depth > *childless_depth {
+                let kind = kind.clone();
+                self.error(line_no, name_start + 1, kind);
+                return;
+            }
+            self.childless = None;
+        }
+
+        if let Some(directive) = content.strip_prefix('%') {
+            self.parse_directive(line_no, name_start, depth, directive);
+            return;
+        }
+
+        if let Some(path) = content.strip_prefix('@') {
+            let parent = self.parent_at(line_no, name_start + 1, depth);
+
+            self.references.push(Reference {
+                parent,
+                index: self.tree.children[parent].len(),
+                path: path.trim().to_string(),
+                file: self.file.clone(),
+                line: line_no,
+                column: name_start + 1,
+            });
+            self.childless = Some((self.stack.len(), ParseErrorKind::ChildOfReference));
+            return;
+        }
+
+        let (name, attributes) = match split_attributes(content) {
+            Ok(split) => split,
+            Err(offset) => {
+                self.error(
+                    line_no,
+                    name_start + offset + 1,
+                    ParseErrorKind::BadAttributes,
+                );
+                return;
+            },
+        };
+
+        let mut spellings = Vec::new();
+        for spelling in split_unquoted(name, '|') {
+            match unquote(spelling.trim()) {
+                Some(spelling) => spellings.push(spelling),
+                None => {
+                    self.error(line_no, name_start + 1, ParseErrorKind::BadQuotes);
+                    return;
+                },
+            }
+        }
+
+        let name = spellings[0].as_str();
+        let aliases: Vec<&str> = spellings[1..].iter().map(|s| s.as_str()).collect();
+
+        let matchers = match parse_spellings(name, &aliases) {
+            Ok(matchers) => matchers,
+            Err(kind) => {
+                self.error(line_no, name_start + 1, kind);
+                return;
+            },
+        };
+
+        let parent = self.parent_at(line_no, name_start + 1, depth);
+
+        let is_new = self.siblings.entry(parent)
+            .or_default()
+            .insert(name.to_string());
+
+        if !is_new {
+            self.error(
+                line_no,
+                name_start + 1,
+                ParseErrorKind::DuplicateSibling(name.to_string()),
+            );
+
+            // Let any children merge into the first occurrence
+            let existing = *self.tree.children(parent).iter()
+                .find(|s| self.tree.name(**s) == name)
+                .unwrap();
+            self.stack.push(existing);
+            return;
+        }
+
+        let new_style = self.tree.add_style(parent, name);
+        self.tree.set_spellings(
+            new_style,
+            aliases.iter().map(|a| a.to_string()).collect(),
+            matchers,
+        );
+
+        for (key, value) in attributes {
+            let value = value.unwrap_or_default();
+            if let Err(kind) = set_attribute(self.tree, new_style, &key, &value) {
+                self.error(line_no, name_start + 1, kind);
+            }
+        }
+
+        self.stack.push(new_style);
+    }
+
+    // Parent of a line at depth. Lines that are indented too far are
+    // reported and treated as children of the deepest possible parent.
+    fn parent_at(&mut self, line_no: usize, column: usize, depth: usize) -> Style {
+        if depth > self.stack.len() {
+            self.error(line_no, column, ParseErrorKind::TooDeep {
+                depth,
+                max: self.stack.len(),
+            });
+        }
+
+        // remove from stack until we are at parent in path
+        while self.stack.len() > depth {
+            self.stack.pop();
+        }
+
+        match self.stack.last() {
+            Some(parent) => *parent,
+            None => self.base,
+        }
+    }
+
+    fn link_references(&mut self) {
+        let references = std::mem::take(&mut self.references);
+
+        for (reference, kind) in link_references(self.tree, references) {
+            self.errors.push(ParseError {
+                file: reference.file,
+                line: reference.line,
+                column: reference.column,
+                kind,
+            });
+        }
+    }
+
+    fn parse_directive(&mut self, line_no: usize, start: usize, depth: usize, directive: &str) {
+        let mut words = directive.split_whitespace();
+        let key = words.next().unwrap_or("");
+        let value: Vec<&str> = words.collect();
+        let value = value.join(" ");
+
+        match key {
+            "include" => {
+                // Paths may contain spaces, so take the rest of the line as is
+                let target = directive["include".len()..].trim();
+                if target.is_empty() {
+                    self.error(line_no, start + 1, bad_value(key, target));
+                } else {
+                    self.include(line_no, start + 1, depth, target);
+                    self.childless = Some((self.stack.len(), ParseErrorKind::ChildOfInclude));
+                }
+            },
+            _ => {
+                if let Err(kind) = set_option(self.tree, key, &value) {
+                    self.error(line_no, start + 1, kind);
+                }
+            },
+        }
+    }
+
+    // Returns the depth of the line and the byte offset of its name, or None
+    // if the indentation is unusable. The first indented line fixes the unit
+    // unless one was given up front.
+    fn measure_indent(&mut self, line_no: usize, line: &str) -> Option<(usize, usize)> {
+        let name_start = line.find(|c| c != '\t' && c != ' ').unwrap_or(line.len());
+        let leading = &line[..name_start];
+
+        if leading.is_empty() {
+            return Some((0, 0));
+        }
+
+        let tab = leading.find('\t');
+        let space = leading.find(' ');
+
+        let found = match (tab, space) {
+            (Some(_), None) => Indent::Tabs,
+            (None, Some(_)) => Indent::Spaces(leading.len()),
+            (Some(t), Some(s)) => {
+                self.error(
+                    line_no,
+                    std::cmp::max(t, s) + 1,
+                    ParseErrorKind::MixedIndent,
+                );
+                return None;
+            },
+            (None, None) => unreachable!(),
+        };
+
+        let unit = match self.indent {
+            Some(unit) => unit,
+            None => {
+                self.indent = Some(found);
+                found
+            },
+        };
+
+        match (unit, found) {
+            (Indent::Tabs, Indent::Tabs) => Some((leading.len(), name_start)),
+            (Indent::Spaces(width), Indent::Spaces(n)) => {
+                if n % width != 0 {
+                    self.error(
+                        line_no,
+                        1,
+                        ParseErrorKind::UnevenIndent { width, found: n },
+                    );
+                    None
+                } else {
+                    Some((n / width, name_start))
+                }
+            },
+            (expected, _) => {
+                self.error(
+                    line_no,
+                    1,
+                    ParseErrorKind::InconsistentIndent { expected },
+                );
+                None
+            },
+        }
+    }
+
+    fn error(&mut self, line: usize, column: usize, kind: ParseErrorKind) {
+        self.errors.push(ParseError {
+            file: self.file.clone(),
+            line,
+            column,
+            kind,
+        });
+    }
+}
+
+// Everything from a '#' that starts the line or follows whitespace is a comment.
+// A '#' inside a name (e.g. "C#") or in quotes is kept.
+pub fn strip_comment(line: &str) -> &str {
+    let mut prev_ws = true;
+
+    for (i, c, quoted) in quoted_chars(line) {
+        if c == '#' && prev_ws && !quoted {
+            return &line[..i];
+        }
+        prev_ws = c.is_whitespace();
+    }
+
+    line
+}
+
+// Characters after which a '"' opens a quoted spelling or value
+const QUOTE_OPENERS: &[char] = &['|', '@', '>', '='];
+
+// Each character of s with its byte offset and whether it is quoted. A '"'
+// at the start of a spelling or value opens a quote, in which '\' escapes
+// '"' and '\'. Anywhere else, as in 12" Mix, a '"' is just a character.
+fn quoted_chars(s: &str) -> Vec<(usize, char, bool)> {
+    let mut chars = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut prev: Option<char> = None;     // Last unquoted non-whitespace character
+
+    for (i, c) in s.char_indices() {
+        if quoted {
+            chars.push((i, c, true));
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = false;
+                prev = Some(c);
+            }
+        } else if c == '"' && (prev.is_none() || QUOTE_OPENERS.contains(&prev.unwrap())) {
+            quoted = true;
+            chars.push((i, c, true));
+        } else {
+            chars.push((i, c, false));
+            if !c.is_whitespace() {
+                prev = Some(c);
+            }
+        }
+    }
+
+    chars
+}
+
+// Splits s at every sep that isn't quoted
+pub fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for (i, c, quoted) in quoted_chars(s) {
+        if c == sep && !quoted {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+// "\"Rock #1\"" -> "Rock #1". A spelling that isn't quoted is returned as
+// it is. None if the closing quote is missing or isn't the last character.
+pub fn unquote(s: &str) -> Option<String> {
+    let inner = match s.strip_prefix('"') {
+        Some(inner) => inner,
+        None => return Some(s.to_string()),
+    };
+
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ '"') | Some(escaped @ '\\') => unquoted.push(escaped),
+                Some(other) => {
+                    unquoted.push('\\');
+                    unquoted.push(other);
+                },
+                None => return None,
+            },
+            '"' => return match chars.next() {
+                None => Some(unquoted),
+                Some(_) => None,
+            },
+            c => unquoted.push(c),
+        }
+    }
+
+    None
+}
+
+// Always quoted, for attribute values
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Quoted only if it would otherwise be read as something else: a comment,
+// directive, link, alias, attributes, a link path separator or different
+// spacing
+pub fn quote_spelling(s: &str) -> String {
+    let special = s.trim() != s
+        || s.starts_with(&['%', '@'][..])
+        || s.contains(&['"', '|', '>', '{', '}'][..])
+        || strip_comment(s).len() != s.len();
+
+    if special {
+        quote(s)
+    } else {
+        s.to_string()
+    }
+}
+
+fn parse_match(value: &str) -> Option<Match> {
+    match value {
+        "leaves" => Some(Match::Leaves),
+        "any" => Some(Match::Any),
+        _ => None,
+    }
+}
+
+fn parse_sort(value: &str) -> Option<Sort> {
+    match value {
+        "file" => Some(Sort::File),
+        "alpha" => Some(Sort::Alpha),
+        _ => None,
+    }
+}
+
+fn parse_pin(value: &str) -> Option<Pin> {
+    match value {
+        "top" => Some(Pin::Top),
+        "bottom" => Some(Pin::Bottom),
+        _ => None,
+    }
+}
+
+fn bad_value(key: &str, value: &str) -> ParseErrorKind {
+    ParseErrorKind::BadValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
+
+// Settings for the whole tree, "%key value" in genre files
+pub fn set_option(tree: &mut StyleTree, key: &str, value: &str) -> Result<(), ParseErrorKind> {
+    match key {
+        "match" => match parse_match(value) {
+            Some(m) => tree.matching = m,
+            None => return Err(bad_value(key, value)),
+        },
+        "separators" => {
+            tree.separators = value.split_whitespace()
+                .map(|s| s.to_string())
+                .collect();
+        },
+        "normalize" => match Normalize::parse(value) {
+            Some(n) => tree.normalize = n,
+            None => return Err(bad_value(key, value)),
+        },
+        "sort" => match parse_sort(value) {
+            Some(sort) => tree.sorting = sort,
+            None => return Err(bad_value(key, value)),
+        },
+        _ => return Err(ParseErrorKind::UnknownDirective(key.to_string())),
+    }
+
+    Ok(())
+}
+
+// Settings for a single style, "{key=value}" in genre files
+pub fn set_attribute(
+    tree: &mut StyleTree,
+    style: Style,
+    key: &str,
+    value: &str,
+) -> Result<(), ParseErrorKind> {
+    match key {
+        "match" => match parse_match(value) {
+            Some(m) => tree.node_matching[style] = Some(m),
+            None => return Err(bad_value(key, value)),
+        },
+        "color" => {
+            if !COLORS.contains(&value) {
+                return Err(bad_value(key, value));
+            }
+            tree.colors[style] = Some(value.to_string());
+        },
+        "description" => tree.descriptions[style] = Some(value.to_string()),
+        "display" => {
+            if value.is_empty() {
+                return Err(bad_value(key, value));
+            }
+            tree.display_names[style] = Some(value.to_string());
+        },
+        "sort" => match parse_sort(value) {
+            Some(sort) => tree.node_sorting[style] = Some(sort),
+            None => return Err(bad_value(key, value)),
+        },
+        "pin" => match parse_pin(value) {
+            Some(pin) => tree.pins[style] = Some(pin),
+            None => return Err(bad_value(key, value)),
+        },
+        _ => return Err(ParseErrorKind::UnknownAttribute(key.to_string())),
+    }
+
+    Ok(())
+}
+
+pub fn parse_spellings(name: &str, aliases: &[&str]) -> Result<Vec<Matcher>, ParseErrorKind> {
+    if name.is_empty() || aliases.iter().any(|a| a.is_empty()) {
+        return Err(ParseErrorKind::EmptyName);
+    }
+
+    std::iter::once(name).chain(aliases.iter().cloned())
+        .map(|spelling| Matcher::parse(spelling).map_err(|reason| ParseErrorKind::BadPattern {
+            pattern: spelling.to_string(),
+            reason,
+        }))
+        .collect()
+}
+
+// Links referenced styles under their new parents, returning the references
+// that could not be linked
+pub fn link_references(
+    tree: &mut StyleTree,
+    references: Vec<Reference>,
+) -> Vec<(Reference, ParseErrorKind)> {
+    let mut failed = Vec::new();
+
+    // References linked so far per parent, which shift later positions
+    let mut linked: HashMap<Style, usize> = HashMap::new();
+
+    for reference in references {
+        let names: Vec<String> = split_unquoted(&reference.path, '>').into_iter()
+            .map(|n| unquote(n.trim()).unwrap_or_default())
+            .collect();
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+
+        let found = if names.iter().any(|n| n.is_empty()) {
+            Vec::new()
+        } else {
+            tree.find_path(&names)
+        };
+
+        let kind = match found.as_slice() {
+            [] => ParseErrorKind::UnknownReference(reference.path.clone()),
+            [target] if tree.children[reference.parent].contains(target) => {
+                ParseErrorKind::DuplicateSibling(tree.name(*target).to_string())
+            },
+            [target] if tree.reaches(*target, reference.parent) => {
+                ParseErrorKind::ReferenceCycle(reference.path.clone())
+            },
+            [target] => {
+                let shift = linked.entry(reference.parent).or_insert(0);
+                tree.children[reference.parent].insert(reference.index + *shift, *target);
+                *shift += 1;
+                continue;
+            },
+            found => ParseErrorKind::AmbiguousReference {
+                path: reference.path.clone(),
+                count: found.len(),
+            },
+        };
+
+        failed.push((reference, kind));
+    }
+
+    failed
+}
+
+pub type Attributes = Vec<(String, Option<String>)>;
+
+// Splits "Name {key=value, flag}" into the name and its attributes. Only a
+// trailing brace group preceded by whitespace and outside quotes counts as
+// attributes, and one left open is malformed. On a malformed group the byte
+// offset of the '{' is returned.
+pub fn split_attributes(content: &str) -> Result<(&str, Attributes), usize> {
+    let chars = quoted_chars(content);
+
+    let last_open = chars.windows(2)
+        .rev()
+        .find(|w| w[1].1 == '{' && !w[1].2 && w[0].1.is_whitespace())
+        .map(|w| w[1].0);
+
+    let open = match (chars.last(), last_open) {
+        (Some((_, '}', false)), Some(open)) => open,
+        // A group that is never closed, as in "A {match=any"
+        (_, Some(open)) if !chars.iter().any(|(i, c, quoted)| *i > open && *c == '}' && !quoted) => {
+            return Err(open);
+        },
+        _ => return Ok((content, Vec::new())),
+    };
+
+    let name = content[..open].trim_end();
+    let inner = &content[open + 1..content.len() - 1];
+
+    if name.is_empty() {
+        return Err(open);
+    }
+
+    let mut attributes = Vec::new();
+
+    for field in split_unquoted(inner, ',') {
+        let field = field.trim();
+        if field.is_empty() {
+            continue;
+        }
+
+        let (key, value) = match field.find('=') {
+            Some(eq) => {
+                let value = field[eq + 1..].trim();
+                let value = if value.starts_with('"') {
+                    unquote(value).ok_or(open)?
+                } else if value.contains('"') {
+                    return Err(open);
+                } else {
+                    value.to_string()
+                };
+                (field[..eq].trim(), Some(value))
+            },
+            None => (field, None),
+        };
+
+        attributes.push((key.to_string(), value));
+    }
+
+    Ok((name, attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_text;
+    use super::*;
+
+    fn parse_with(text: &str, options: &LoadOptions) -> Result<StyleTree, Vec<ParseError>> {
+        let mut tree = StyleTree::new();
+
+        match Parser::new(&mut tree, "test.txt", options).parse(text.as_bytes()) {
+            Ok(()) => Ok(tree),
+            Err(LoadError::Parse(errors)) => Err(errors),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn parse(text: &str) -> Result<StyleTree, Vec<ParseError>> {
+        match parse_text(text) {
+            Ok(tree) => Ok(tree),
+            Err(LoadError::Parse(errors)) => Err(errors),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn errors(text: &str) -> Vec<ParseError> {
+        match parse(text) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors,
+        }
+    }
+
+    fn path_of(tree: &StyleTree, name: &str) -> String {
+        tree.path(tree.find_by_name(name)[0]).join(" > ")
+    }
+
+    // A directory of genre files for the include tests
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("style_freak_parser_{}_{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        dir
+    }
+
+    fn load(path: &Path) -> Result<StyleTree, Vec<ParseError>> {
+        match StyleTree::load(path.to_str().unwrap(), &LoadOptions::default()) {
+            Ok(tree) => Ok(tree),
+            Err(LoadError::Parse(errors)) => Err(errors),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_their_line_and_column() {
+        let errors = errors("House\n\n# Deep\n\t\t\tDeep House\nTechno\nTechno\n");
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].kind, ParseErrorKind::TooDeep { depth: 3, max: 1 }));
+        assert_eq!((errors[0].line, errors[0].column), (4, 4));
+        assert!(matches!(&errors[1].kind, ParseErrorKind::DuplicateSibling(name) if name == "Techno"));
+        assert_eq!((errors[1].line, errors[1].column), (6, 1));
+        assert_eq!(errors[1].to_string(), "test.txt:6:1: \"Techno\" already exists under the same parent");
+    }
+
+    #[test]
+    fn tabs_are_detected() {
+        let tree = parse("House\n\tDeep House\n\t\tDub House\nTechno\n").unwrap();
+
+        assert_eq!(path_of(&tree, "Dub House"), "House > Deep House > Dub House");
+        assert_eq!(path_of(&tree, "Techno"), "Techno");
+    }
+
+    #[test]
+    fn space_width_is_detected_from_the_first_indented_line() {
+        let tree = parse("House\n   Deep House\n      Dub House\n   Acid House\n").unwrap();
+
+        assert_eq!(path_of(&tree, "Dub House"), "House > Deep House > Dub House");
+        assert_eq!(path_of(&tree, "Acid House"), "House > Acid House");
+    }
+
+    #[test]
+    fn given_indent_overrides_detection() {
+        let options = LoadOptions {
+            indent: Some(Indent::Spaces(2)),
+            ..LoadOptions::default()
+        };
+        let tree = parse_with("House\n    Deep House\n", &options);
+
+        // Two levels down with nothing in between
+        assert!(matches!(tree.err().unwrap()[0].kind, ParseErrorKind::TooDeep { depth: 2, max: 1 }));
+    }
+
+    #[test]
+    fn mixed_indent_is_an_error() {
+        let errors = errors("House\n\t Deep House\n");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::MixedIndent));
+        assert_eq!((errors[0].line, errors[0].column), (2, 2));
+    }
+
+    #[test]
+    fn uneven_indent_is_an_error() {
+        let errors = errors("House\n  Deep House\n   Dub House\n");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::UnevenIndent { width: 2, found: 3 }));
+        assert_eq!(errors[0].line, 3);
+    }
+
+    #[test]
+    fn switching_indent_is_an_error() {
+        let errors = errors("House\n\tDeep House\nTechno\n  Minimal\n");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::InconsistentIndent { expected: Indent::Tabs }));
+        assert_eq!(errors[0].line, 4);
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        let tree = parse("# Genres\nC# Jazz # named after the key\n\"Rock #1\" # quoted\nHouse#Deep\n\t# nothing here\n").unwrap();
+
+        assert_eq!(tree.children(tree.base()).len(), 3);
+        assert_eq!(path_of(&tree, "C# Jazz"), "C# Jazz");
+        assert_eq!(path_of(&tree, "Rock #1"), "Rock #1");
+        assert_eq!(path_of(&tree, "House#Deep"), "House#Deep");
+    }
+
+    #[test]
+    fn strip_comment_leaves_quotes_alone() {
+        assert_eq!(strip_comment("A | \"B #2\" # note"), "A | \"B #2\" ");
+        assert_eq!(strip_comment("12\" Mix # note"), "12\" Mix ");
+        assert_eq!(strip_comment("# all of it"), "");
+    }
+
+    #[test]
+    fn unterminated_attributes_are_an_error() {
+        let errors = errors("House\n\tA {match=any\n");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, ParseErrorKind::BadAttributes));
+        assert_eq!((errors[0].line, errors[0].column), (2, 4));
+
+        // Braces inside the name, or quoted, are still part of it
+        let tree = parse("A {live} B\n\"C {x\"\nD{x\n").unwrap();
+        assert_eq!(tree.children(tree.base()).len(), 3);
+        assert_eq!(path_of(&tree, "C {x"), "C {x");
+    }
+
+    #[test]
+    fn links_resolve_anywhere_in_the_file() {
+        let tree = parse("Techno\n\t@House > Tech House\n\tMinimal\nHouse\n\tTech House\n").unwrap();
+        let techno = tree.find_by_name("Techno")[0];
+        let tech_house = tree.find_by_name("Tech House")[0];
+
+        // In place of the link line, ahead of Minimal
+        assert_eq!(tree.children(techno)[0], tech_house);
+        assert!(tree.is_link(techno, tech_house));
+        assert_eq!(path_of(&tree, "Tech House"), "House > Tech House");
+    }
+
+    #[test]
+    fn bad_links_are_errors() {
+        let unknown = errors("House\nTechno\n\t@Trance\n");
+        assert!(matches!(&unknown[0].kind, ParseErrorKind::UnknownReference(path) if path == "Trance"));
+        assert_eq!(unknown[0].line, 3);
+
+        let ambiguous = errors("House\n\tFusion\nTechno\n\tFusion\nTrance\n\t@Fusion\n");
+        assert!(matches!(ambiguous[0].kind, ParseErrorKind::AmbiguousReference { count: 2, .. }));
+
+        // Giving more of the path settles it
+        assert!(parse("House\n\tFusion\nTechno\n\tFusion\nTrance\n\t@Techno > Fusion\n").is_ok());
+
+        let cycle = errors("House\n\tDeep House\n\t\t@House\n");
+        assert!(matches!(cycle[0].kind, ParseErrorKind::ReferenceCycle(_)));
+
+        let child = errors("House\nTechno\n\t@House\n\t\tDeep House\n");
+        assert!(matches!(child[0].kind, ParseErrorKind::ChildOfReference));
+        assert_eq!(child[0].line, 4);
+    }
+
+    #[test]
+    fn includes_graft_under_their_parent() {
+        let dir = write_files("graft", &[
+            ("genres.txt", "Electronic\n\t%include house.txt\n\tTechno\nRock\n"),
+            ("house.txt", "House\n  Deep House\nAcid House\n"),
+        ]);
+        let tree = load(&dir.join("genres.txt")).unwrap();
+
+        assert_eq!(path_of(&tree, "Deep House"), "Electronic > House > Deep House");
+        assert_eq!(path_of(&tree, "Acid House"), "Electronic > Acid House");
+        assert_eq!(path_of(&tree, "Techno"), "Electronic > Techno");
+        assert_eq!(tree.files().len(), 2);
+    }
+
+    #[test]
+    fn include_errors_name_the_included_file() {
+        let dir = write_files("errors", &[
+            ("genres.txt", "Electronic\n\t%include house.txt\n\t\tTechno\n"),
+            ("house.txt", "House\n\t\tDeep House\n"),
+        ]);
+        let errors = load(&dir.join("genres.txt")).err().unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].kind, ParseErrorKind::ChildOfInclude));
+        assert!(errors[0].file.ends_with("genres.txt"));
+        assert!(matches!(errors[1].kind, ParseErrorKind::TooDeep { .. }));
+        assert!(errors[1].file.ends_with("house.txt"));
+        assert_eq!(errors[1].line, 2);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = write_files("cycle", &[
+            ("genres.txt", "Electronic\n\t%include house.txt\n"),
+            ("house.txt", "House\n\t%include genres.txt\n"),
+        ]);
+        let errors = load(&dir.join("genres.txt")).err().unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0].kind, ParseErrorKind::IncludeCycle(path) if path.ends_with("genres.txt")));
+
+        let missing = write_files("missing", &[("genres.txt", "%include nowhere.txt\n")]);
+        let errors = load(&missing.join("genres.txt")).err().unwrap();
+        assert!(matches!(errors[0].kind, ParseErrorKind::IncludeFailed { .. }));
+    }
+}
@@ -0,0 +1,426 @@
+use std::fs;
+
+use super::parser::quote_spelling;
+use super::parser::split_attributes;
+use super::parser::split_unquoted;
+use super::parser::strip_comment;
+use super::parser::unquote;
+use super::parser::Parser;
+use super::Format;
+use super::Indent;
+use super::LoadError;
+use super::LoadOptions;
+use super::StyleTree;
+
+// A text genre file held as its lines, so it can be edited without losing
+// comments, directives or layout. Genres are addressed by their index in
+// entries().
+pub struct Source {
+    path: String,
+    options: LoadOptions,
+    lines: Vec<String>,
+    indent: Indent,
+}
+
+// A genre or link line
+pub struct Entry {
+    pub line: usize,
+    pub depth: usize,
+    pub name: String,
+    pub is_link: bool,
+}
+
+impl Source {
+    // Included files would have to be edited separately, so files with
+    // includes are refused
+    pub fn open(path: &str, options: &LoadOptions) -> Result<Source, String> {
+        if options.format.unwrap_or_else(|| Format::from_path(path)) != Format::Text {
+            return Err("only text genre files can be edited".to_string());
+        }
+
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        Source::new(path, &text, options)
+    }
+
+    fn new(path: &str, text: &str, options: &LoadOptions) -> Result<Source, String> {
+        let lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+
+        if lines.iter().any(|l| strip_comment(l).trim().starts_with("%include")) {
+            return Err("genre files with %include can't be edited".to_string());
+        }
+
+        // Like the parser, go by the first indented line
+        let detected = lines.iter()
+            .map(|l| strip_comment(l).trim_end())
+            .filter(|l| !l.trim_start().is_empty())
+            .map(|l| &l[..l.len() - l.trim_start().len()])
+            .find(|leading| !leading.is_empty())
+            .map(|leading| match leading.starts_with('\t') {
+                true => Indent::Tabs,
+                false => Indent::Spaces(leading.len()),
+            });
+
+        Ok(Source {
+            path: path.to_string(),
+            options: LoadOptions {
+                indent: options.indent,
+                format: Some(Format::Text),
+            },
+            lines,
+            indent: options.indent.or(detected).unwrap_or(Indent::Tabs),
+        })
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let content = strip_comment(line).trim();
+            if content.is_empty() || content.starts_with('%') {
+                continue;
+            }
+
+            let is_link = content.starts_with('@');
+            let name = if is_link {
+                content.to_string()
+            } else {
+                let spelling = first_spelling(content);
+                unquote(spelling).unwrap_or_else(|| spelling.to_string())
+            };
+
+            entries.push(Entry {
+                line: i,
+                depth: self.depth_of(line),
+                name,
+                is_link,
+            });
+        }
+
+        entries
+    }
+
+    // Adds a genre after entry's subtree, as its last child or its next
+    // sibling. Names the line format would misread are quoted. Returns the
+    // new genre's entry.
+    pub fn add(&mut self, entry: Option<usize>, child: bool, name: &str) -> Option<usize> {
+        let entries = self.entries();
+
+        let (line, depth) = match entry.map(|e| &entries[e]) {
+            Some(e) => (self.subtree_end(&entries, e.line), e.depth + child as usize),
+            None => (self.lines.len(), 0),
+        };
+
+        self.lines.insert(line, format!("{}{}", self.prefix(depth), quote_spelling(name)));
+        self.entry_at_line(line)
+    }
+
+    // Replaces the name, keeping aliases, attributes and comments
+    pub fn rename(&mut self, entry: usize, name: &str) -> Result<(), String> {
+        let entries = self.entries();
+        let e = &entries[entry];
+        if e.is_link {
+            return Err("links can't be renamed".to_string());
+        }
+
+        let line = &self.lines[e.line];
+        let start = line.len() - line.trim_start().len();
+        let end = start + first_spelling(strip_comment(&line[start..]).trim_end()).len();
+
+        self.lines[e.line].replace_range(start..end, &quote_spelling(name));
+        Ok(())
+    }
+
+    // Removes entry along with everything under it
+    pub fn delete(&mut self, entry: usize) {
+        let entries = self.entries();
+        let start = entries[entry].line;
+        let end = self.subtree_end(&entries, start);
+
+        self.lines.drain(start..end);
+    }
+
+    // Swaps entry's subtree with the one of its previous sibling
+    pub fn move_up(&mut self, entry: usize) -> Option<usize> {
+        let entries = self.entries();
+        let prev = self.prev_sibling(&entries, entry)?;
+
+        let first = (entries[prev].line, self.subtree_end(&entries, entries[prev].line));
+        let second = (entries[entry].line, self.subtree_end(&entries, entries[entry].line));
+        self.swap_blocks(first, second);
+
+        self.entry_at_line(first.0)
+    }
+
+    pub fn move_down(&mut self, entry: usize) -> Option<usize> {
+        let entries = self.entries();
+        let next = self.next_sibling(&entries, entry)?;
+
+        let first = (entries[entry].line, self.subtree_end(&entries, entries[entry].line));
+        let second = (entries[next].line, self.subtree_end(&entries, entries[next].line));
+        let moved_to = self.swap_blocks(first, second);
+
+        self.entry_at_line(moved_to)
+    }
+
+    // Makes entry the last child of its previous sibling
+    pub fn indent(&mut self, entry: usize) -> Option<usize> {
+        let entries = self.entries();
+        let prev = self.prev_sibling(&entries, entry)?;
+        if entries[prev].is_link {
+            return None;
+        }
+
+        let start = entries[entry].line;
+        let end = self.subtree_end(&entries, start);
+        let prefix = self.prefix(1);
+
+        for line in &mut self.lines[start..end] {
+            if !line.trim().is_empty() {
+                line.insert_str(0, &prefix);
+            }
+        }
+
+        Some(entry)
+    }
+
+    // Makes entry the next sibling of its parent
+    pub fn outdent(&mut self, entry: usize) -> Option<usize> {
+        let entries = self.entries();
+        let depth = entries[entry].depth;
+        if depth == 0 {
+            return None;
+        }
+
+        let parent = entries[..entry].iter().rposition(|e| e.depth < depth)?;
+        let parent_end = self.subtree_end(&entries, entries[parent].line);
+
+        let start = entries[entry].line;
+        let end = self.subtree_end(&entries, start);
+
+        let mut block: Vec<String> = self.lines.drain(start..end).collect();
+        for line in &mut block {
+            *line = self.dedent(line);
+        }
+
+        let line = parent_end - block.len();
+        for (i, moved) in block.into_iter().enumerate() {
+            self.lines.insert(line + i, moved);
+        }
+
+        self.entry_at_line(line)
+    }
+
+    // Parses the edited lines to catch anything that would stop the file
+    // from loading, such as duplicate siblings or links to renamed genres
+    pub fn check(&self) -> Result<(), LoadError> {
+        let mut tree = StyleTree::new();
+        let text = self.lines.join("\n");
+
+        Parser::new(&mut tree, &self.path, &self.options).parse(text.as_bytes())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        self.check().map_err(|e| e.to_string().lines().next().unwrap_or("").to_string())?;
+
+        fs::write(&self.path, self.lines.join("\n") + "\n")
+            .map_err(|e| format!("{}: {}", self.path, e))
+    }
+
+    fn depth_of(&self, line: &str) -> usize {
+        let leading = &line[..line.len() - line.trim_start().len()];
+
+        match self.indent {
+            Indent::Tabs => leading.chars().filter(|c| *c == '\t').count(),
+            Indent::Spaces(width) => leading.len() / width,
+        }
+    }
+
+    fn prefix(&self, depth: usize) -> String {
+        match self.indent {
+            Indent::Tabs => "\t".repeat(depth),
+            Indent::Spaces(width) => " ".repeat(width * depth),
+        }
+    }
+
+    fn dedent(&self, line: &str) -> String {
+        let unit = self.prefix(1);
+        line.strip_prefix(unit.as_str()).unwrap_or(line).to_string()
+    }
+
+    // Line after the last genre under the one at line. Comments and blank
+    // lines that follow are left where they are.
+    fn subtree_end(&self, entries: &[Entry], line: usize) -> usize {
+        let i = entries.iter().position(|e| e.line == line).unwrap();
+        let depth = entries[i].depth;
+
+        entries[i + 1..].iter()
+            .take_while(|e| e.depth > depth)
+            .last()
+            .map(|e| e.line + 1)
+            .unwrap_or(line + 1)
+    }
+
+    fn prev_sibling(&self, entries: &[Entry], entry: usize) -> Option<usize> {
+        let depth = entries[entry].depth;
+
+        entries[..entry].iter()
+            .rposition(|e| e.depth <= depth)
+            .filter(|i| entries[*i].depth == depth)
+    }
+
+    fn next_sibling(&self, entries: &[Entry], entry: usize) -> Option<usize> {
+        let depth = entries[entry].depth;
+
+        entries.iter().enumerate().skip(entry + 1)
+            .find(|(_, e)| e.depth <= depth)
+            .map(|(i, _)| i)
+            .filter(|i| entries[*i].depth == depth)
+    }
+
+    // Swaps two blocks of lines, the first ending before the second starts.
+    // Returns where the first block's lines start afterwards.
+    fn swap_blocks(&mut self, first: (usize, usize), second: (usize, usize)) -> usize {
+        let mut swapped: Vec<String> = self.lines[second.0..second.1].to_vec();
+        swapped.extend_from_slice(&self.lines[first.1..second.0]);
+        swapped.extend_from_slice(&self.lines[first.0..first.1]);
+
+        self.lines.splice(first.0..second.1, swapped);
+
+        first.0 + (second.1 - second.0) + (second.0 - first.1)
+    }
+
+    fn entry_at_line(&self, line: usize) -> Option<usize> {
+        self.entries().iter().position(|e| e.line == line)
+    }
+}
+
+// "Name | Alias {key=value}" -> "Name", still quoted if it is
+fn first_spelling(content: &str) -> &str {
+    let name = match split_attributes(content) {
+        Ok((name, _)) => name,
+        Err(_) => content,
+    };
+
+    split_unquoted(name, '|')[0].trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(text: &str) -> Source {
+        Source::new("test.txt", text, &LoadOptions::default()).unwrap()
+    }
+
+    fn text(source: &Source) -> String {
+        source.lines.join("\n") + "\n"
+    }
+
+    fn names(source: &Source) -> Vec<String> {
+        source.entries().into_iter().map(|e| e.name).collect()
+    }
+
+    #[test]
+    fn add_as_child_or_sibling() {
+        let mut s = source("Electronic\n\tHouse\nRock\n");
+
+        assert_eq!(s.add(Some(0), true, "Techno"), Some(2));
+        assert_eq!(s.add(Some(0), false, "Jazz"), Some(3));
+        assert_eq!(s.add(None, false, "Pop"), Some(5));
+        assert_eq!(text(&s), "Electronic\n\tHouse\n\tTechno\nJazz\nRock\nPop\n");
+        assert!(s.check().is_ok());
+    }
+
+    #[test]
+    fn add_quotes_names_the_format_would_misread() {
+        let mut s = source("Electronic\n");
+
+        for name in &["#x", "%match any", "@Electronic", "Pop | Rock", "R&B {live}", "Foo #bar"] {
+            assert!(s.add(Some(0), true, name).is_some());
+        }
+
+        assert_eq!(
+            names(&s),
+            ["Electronic", "#x", "%match any", "@Electronic", "Pop | Rock", "R&B {live}", "Foo #bar"],
+        );
+        assert!(s.check().is_ok());
+    }
+
+    #[test]
+    fn rename_keeps_aliases_attributes_and_comments() {
+        let mut s = source("Electronic\n\tDrum & Bass | DnB {color=red} # fast\n");
+
+        s.rename(1, "Jungle").unwrap();
+        assert_eq!(text(&s), "Electronic\n\tJungle | DnB {color=red} # fast\n");
+
+        s.rename(1, "Foo #bar").unwrap();
+        assert_eq!(names(&s), ["Electronic", "Foo #bar"]);
+        assert_eq!(text(&s), "Electronic\n\t\"Foo #bar\" | DnB {color=red} # fast\n");
+
+        s.rename(1, "Plain").unwrap();
+        assert_eq!(text(&s), "Electronic\n\tPlain | DnB {color=red} # fast\n");
+    }
+
+    #[test]
+    fn rename_refuses_links() {
+        let mut s = source("Electronic\n\tHouse\nDance\n\t@Electronic > House\n");
+
+        assert!(s.rename(3, "Techno").is_err());
+    }
+
+    #[test]
+    fn delete_takes_the_subtree() {
+        let mut s = source("Electronic\n\tHouse\n\t\tDeep House\nRock\n");
+
+        s.delete(1);
+        assert_eq!(text(&s), "Electronic\nRock\n");
+    }
+
+    #[test]
+    fn move_swaps_subtrees_with_siblings() {
+        let mut s = source("Electronic\n\tHouse\n\t\tDeep House\n\tTechno\nRock\n");
+
+        assert_eq!(s.move_down(1), Some(2));
+        assert_eq!(text(&s), "Electronic\n\tTechno\n\tHouse\n\t\tDeep House\nRock\n");
+
+        assert_eq!(s.move_up(2), Some(1));
+        assert_eq!(text(&s), "Electronic\n\tHouse\n\t\tDeep House\n\tTechno\nRock\n");
+
+        // No sibling to swap with
+        assert_eq!(s.move_up(1), None);
+        assert_eq!(s.move_down(3), None);
+        assert_eq!(s.move_down(4), None);
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut s = source("Electronic\n\tHouse\n\tDeep House\n\t\tUK Deep\nRock\n");
+
+        assert_eq!(s.indent(2), Some(2));
+        assert_eq!(text(&s), "Electronic\n\tHouse\n\t\tDeep House\n\t\t\tUK Deep\nRock\n");
+
+        assert_eq!(s.outdent(2), Some(2));
+        assert_eq!(text(&s), "Electronic\n\tHouse\n\tDeep House\n\t\tUK Deep\nRock\n");
+
+        // Already at the top, or nothing to indent under
+        assert_eq!(s.outdent(0), None);
+        assert_eq!(s.indent(0), None);
+    }
+
+    #[test]
+    fn outdent_moves_after_the_parents_subtree() {
+        let mut s = source("Electronic\n\tHouse\n\tTechno\nRock\n");
+
+        assert_eq!(s.outdent(1), Some(2));
+        assert_eq!(text(&s), "Electronic\n\tTechno\nHouse\nRock\n");
+    }
+
+    #[test]
+    fn spaces_are_kept() {
+        let mut s = source("Electronic\n  House\n");
+
+        s.add(Some(1), true, "Deep House");
+        assert_eq!(text(&s), "Electronic\n  House\n    Deep House\n");
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::time::SystemTime;
+
+// Notices changes to a set of files by polling their modification times,
+// which is cheap enough to do on every tick
+pub struct Watcher {
+    files: Vec<(String, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    pub fn new(files: &[String]) -> Watcher {
+        let mut watcher = Watcher { files: Vec::new() };
+        watcher.watch(files);
+        watcher
+    }
+
+    pub fn watch(&mut self, files: &[String]) {
+        self.files = files.iter()
+            .map(|f| (f.clone(), modified(f)))
+            .collect();
+    }
+
+    // Whether any file was modified, created or removed since last asked
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+
+        for (file, time) in &mut self.files {
+            let now = modified(file);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}